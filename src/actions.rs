@@ -1,19 +1,42 @@
 use anyhow::{Result, anyhow};
+#[cfg(target_os = "linux")]
+use evdev::{
+    AttributeSet, EventType, InputEvent, KeyCode, RelativeAxisCode, uinput::VirtualDevice,
+};
 use log::{info, warn};
 
+use crate::config::WheelMode;
+
+/// Hi-res wheel units per legacy detent (kernel convention).
+pub const WHEEL_UNITS_PER_DETENT: i32 = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollAxis {
+    Vertical,
+    Horizontal,
+}
+
 pub struct UinputSink {
     enabled: bool,
-    #[allow(dead_code)]
+    wheel: WheelMode,
+    /// Hi-res units not yet emitted as a legacy detent, per axis (h, v).
+    detent_rem: (i32, i32),
     linux: Option<Box<LinuxUinput>>,
 }
 
 impl UinputSink {
     pub fn new() -> Result<Self> {
+        Self::with_wheel_mode(WheelMode::default())
+    }
+
+    pub fn with_wheel_mode(wheel: WheelMode) -> Result<Self> {
         #[cfg(target_os = "linux")]
         {
-            let dev = LinuxUinput::create()?;
+            let dev = LinuxUinput::create(wheel)?;
             return Ok(Self {
                 enabled: true,
+                wheel,
+                detent_rem: (0, 0),
                 linux: Some(Box::new(dev)),
             });
         }
@@ -22,6 +45,8 @@ impl UinputSink {
             warn!("uinput not available; running in NO-OP mode");
             Ok(Self {
                 enabled: true,
+                wheel,
+                detent_rem: (0, 0),
                 linux: None,
             })
         }
@@ -30,6 +55,8 @@ impl UinputSink {
     pub fn noop() -> Self {
         Self {
             enabled: true,
+            wheel: WheelMode::default(),
+            detent_rem: (0, 0),
             linux: None,
         }
     }
@@ -41,17 +68,72 @@ impl UinputSink {
         self.enabled = en;
     }
 
+    /// Switch wheel axes. Clients pick legacy vs hi-res from the advertised
+    /// capabilities, so the virtual device is recreated when this changes.
+    pub fn set_wheel_mode(&mut self, wheel: WheelMode) -> Result<()> {
+        if wheel == self.wheel {
+            return Ok(());
+        }
+        self.wheel = wheel;
+        self.detent_rem = (0, 0);
+        #[cfg(target_os = "linux")]
+        if self.linux.is_some() {
+            self.linux = Some(Box::new(LinuxUinput::create(wheel)?));
+            info!("uinput: wheel mode now {wheel:?}");
+        }
+        Ok(())
+    }
+
     pub fn click_right(&mut self) -> Result<()> {
         self.click_mouse("right")
     }
 
     pub fn scroll_vertical(&mut self, steps: i32) -> Result<()> {
-        if !self.enabled {
+        self.scroll_detents(ScrollAxis::Vertical, steps)
+    }
+
+    pub fn scroll_horizontal(&mut self, steps: i32) -> Result<()> {
+        self.scroll_detents(ScrollAxis::Horizontal, steps)
+    }
+
+    /// Scroll whole detents (discrete bindings, `touchctl emit`).
+    fn scroll_detents(&mut self, axis: ScrollAxis, steps: i32) -> Result<()> {
+        if !self.enabled || steps == 0 {
+            return Ok(());
+        }
+        let legacy = if self.wheel.legacy() { steps } else { 0 };
+        let hires = if self.wheel.hires() {
+            steps * WHEEL_UNITS_PER_DETENT
+        } else {
+            0
+        };
+        self.emit_scroll(axis, legacy, hires)
+    }
+
+    /// Scroll by `units` 1/120ths of a detent (continuous two-finger scrolling).
+    /// Legacy detents are emitted whenever a full 120 has accumulated.
+    pub fn scroll_smooth(&mut self, axis: ScrollAxis, units: i32) -> Result<()> {
+        if !self.enabled || units == 0 {
             return Ok(());
         }
+        let rem = match axis {
+            ScrollAxis::Horizontal => &mut self.detent_rem.0,
+            ScrollAxis::Vertical => &mut self.detent_rem.1,
+        };
+        *rem += units;
+        let detents = *rem / WHEEL_UNITS_PER_DETENT;
+        *rem -= detents * WHEEL_UNITS_PER_DETENT;
+
+        let legacy = if self.wheel.legacy() { detents } else { 0 };
+        let hires = if self.wheel.hires() { units } else { 0 };
+        self.emit_scroll(axis, legacy, hires)
+    }
+
+    #[allow(unused_variables)]
+    fn emit_scroll(&mut self, axis: ScrollAxis, detents: i32, units: i32) -> Result<()> {
         #[cfg(target_os = "linux")]
         if let Some(dev) = self.linux.as_mut() {
-            dev.scroll_vertical(steps)?;
+            dev.scroll(axis, detents, units)?;
         }
         Ok(())
     }
@@ -88,32 +170,30 @@ impl UinputSink {
                 keys.push(map_key(&p)?);
             }
             // press in order
-            for k in &keys {
-                dev.key_send(*k, 1)?;
-            }
-            dev.sync()?;
+            dev.keys(&keys, 1)?;
             // release in reverse
-            for k in keys.iter().rev() {
-                dev.key_send(*k, 0)?;
-            }
-            dev.sync()?;
+            keys.reverse();
+            dev.keys(&keys, 0)?;
         }
         Ok(())
     }
 }
 
 #[cfg(target_os = "linux")]
-fn map_key(tok: &str) -> Result<uinput::event::keyboard::Key> {
-    use uinput::event::keyboard::Key as K;
+fn map_key(tok: &str) -> Result<KeyCode> {
     let k = match tok {
-        "CTRL" | "CONTROL" => K::LeftControl,
-        "ALT" => K::LeftAlt,
-        "SHIFT" => K::LeftShift,
-        "SUPER" | "META" | "WIN" => K::LeftMeta,
-        "TAB" => K::Tab,
-        "MINUS" | "-" => K::Minus,
-        "EQUAL" | "=" => K::Equal,
-        // you can add more here later (A..Z, digits, arrows, etc.)
+        "CTRL" | "CONTROL" => KeyCode::KEY_LEFTCTRL,
+        "ALT" => KeyCode::KEY_LEFTALT,
+        "SHIFT" => KeyCode::KEY_LEFTSHIFT,
+        "SUPER" | "META" | "WIN" => KeyCode::KEY_LEFTMETA,
+        "TAB" => KeyCode::KEY_TAB,
+        "MINUS" | "-" => KeyCode::KEY_MINUS,
+        "EQUAL" | "=" => KeyCode::KEY_EQUAL,
+        "LEFT" => KeyCode::KEY_LEFT,
+        "RIGHT" => KeyCode::KEY_RIGHT,
+        "UP" => KeyCode::KEY_UP,
+        "DOWN" => KeyCode::KEY_DOWN,
+        // you can add more here later (A..Z, digits, etc.)
         other => return Err(anyhow!("unsupported key token: {other}")),
     };
     Ok(k)
@@ -121,76 +201,103 @@ fn map_key(tok: &str) -> Result<uinput::event::keyboard::Key> {
 
 #[cfg(target_os = "linux")]
 struct LinuxUinput {
-    dev: uinput::device::Device,
+    dev: VirtualDevice,
 }
 
 #[cfg(target_os = "linux")]
 impl LinuxUinput {
-    fn create() -> Result<Self> {
-        use uinput::event::{controller::Mouse, keyboard, relative};
-
-        let dev = uinput::default()?
-            .name("Touchctl Virtual Input")?
-            // relative axes + wheel
-            .event(relative::Position::X)?
-            .event(relative::Position::Y)?
-            .event(relative::Wheel::Vertical)?
-            .event(relative::Wheel::Horizontal)?
+    fn create(wheel: WheelMode) -> Result<Self> {
+        let mut rel = AttributeSet::<RelativeAxisCode>::new();
+        rel.insert(RelativeAxisCode::REL_X);
+        rel.insert(RelativeAxisCode::REL_Y);
+        if wheel.legacy() {
+            rel.insert(RelativeAxisCode::REL_WHEEL);
+            rel.insert(RelativeAxisCode::REL_HWHEEL);
+        }
+        if wheel.hires() {
+            rel.insert(RelativeAxisCode::REL_WHEEL_HI_RES);
+            rel.insert(RelativeAxisCode::REL_HWHEEL_HI_RES);
+        }
+
+        let mut keys = AttributeSet::<KeyCode>::new();
+        for k in [
             // mouse buttons
-            .event(Mouse::Left)?
-            .event(Mouse::Right)?
-            .event(Mouse::Middle)?
+            KeyCode::BTN_LEFT,
+            KeyCode::BTN_RIGHT,
+            KeyCode::BTN_MIDDLE,
             // keys for our chords
-            .event(keyboard::Key::LeftControl)?
-            .event(keyboard::Key::LeftAlt)?
-            .event(keyboard::Key::LeftShift)?
-            .event(keyboard::Key::LeftMeta)?
-            .event(keyboard::Key::Tab)?
-            .event(keyboard::Key::Minus)?
-            .event(keyboard::Key::Equal)?
-            .create()?;
+            KeyCode::KEY_LEFTCTRL,
+            KeyCode::KEY_LEFTALT,
+            KeyCode::KEY_LEFTSHIFT,
+            KeyCode::KEY_LEFTMETA,
+            KeyCode::KEY_TAB,
+            KeyCode::KEY_MINUS,
+            KeyCode::KEY_EQUAL,
+            KeyCode::KEY_LEFT,
+            KeyCode::KEY_RIGHT,
+            KeyCode::KEY_UP,
+            KeyCode::KEY_DOWN,
+        ] {
+            keys.insert(k);
+        }
+
+        let dev = VirtualDevice::builder()?
+            .name("Touchctl Virtual Input")
+            .with_relative_axes(&rel)?
+            .with_keys(&keys)?
+            .build()?;
 
         info!("uinput: created virtual device");
         Ok(Self { dev })
     }
 
-    fn sync(&mut self) -> Result<()> {
-        self.dev.synchronize()?;
+    /// Set every key in `keys` to `val` within a single frame.
+    fn keys(&mut self, keys: &[KeyCode], val: i32) -> Result<()> {
+        let evs: Vec<_> = keys
+            .iter()
+            .map(|k| InputEvent::new(EventType::KEY.0, k.0, val))
+            .collect();
+        self.dev.emit(&evs)?;
         Ok(())
     }
 
-    fn key_send(&mut self, key: uinput::event::keyboard::Key, val: i32) -> Result<()> {
-        self.dev.send(key, val)?;
-        Ok(())
+    fn click(&mut self, btn: KeyCode) -> Result<()> {
+        self.keys(&[btn], 1)?;
+        self.keys(&[btn], 0)
     }
 
     fn click_left(&mut self) -> Result<()> {
-        use uinput::event::controller::Mouse;
-        self.dev.send(Mouse::Left, 1)?;
-        self.sync()?;
-        self.dev.send(Mouse::Left, 0)?;
-        self.sync()?;
-        Ok(())
+        self.click(KeyCode::BTN_LEFT)
     }
     fn click_right(&mut self) -> Result<()> {
-        use uinput::event::controller::Mouse;
-        self.dev.send(Mouse::Right, 1)?;
-        self.sync()?;
-        self.dev.send(Mouse::Right, 0)?;
-        self.sync()?;
-        Ok(())
+        self.click(KeyCode::BTN_RIGHT)
     }
     fn click_middle(&mut self) -> Result<()> {
-        use uinput::event::controller::Mouse;
-        self.dev.send(Mouse::Middle, 1)?;
-        self.sync()?;
-        self.dev.send(Mouse::Middle, 0)?;
-        self.sync()?;
-        Ok(())
+        self.click(KeyCode::BTN_MIDDLE)
     }
-    fn scroll_vertical(&mut self, steps: i32) -> Result<()> {
-        use uinput::event::relative::Wheel;
-        self.dev.send(Wheel::Vertical, steps)?;
-        self.sync()
+
+    /// Emit legacy detents and/or hi-res units in one frame; zeros are skipped.
+    fn scroll(&mut self, axis: ScrollAxis, detents: i32, units: i32) -> Result<()> {
+        let (legacy, hires) = match axis {
+            ScrollAxis::Vertical => (
+                RelativeAxisCode::REL_WHEEL,
+                RelativeAxisCode::REL_WHEEL_HI_RES,
+            ),
+            ScrollAxis::Horizontal => (
+                RelativeAxisCode::REL_HWHEEL,
+                RelativeAxisCode::REL_HWHEEL_HI_RES,
+            ),
+        };
+        let mut evs = Vec::with_capacity(2);
+        if units != 0 {
+            evs.push(InputEvent::new(EventType::RELATIVE.0, hires.0, units));
+        }
+        if detents != 0 {
+            evs.push(InputEvent::new(EventType::RELATIVE.0, legacy.0, detents));
+        }
+        if !evs.is_empty() {
+            self.dev.emit(&evs)?;
+        }
+        Ok(())
     }
 }
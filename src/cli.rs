@@ -1,6 +1,6 @@
 use anyhow::{Result, anyhow};
 use pico_args::Arguments;
-use std::{env, process::Command};
+use std::{env, path::Path, process::Command};
 
 use crate::ipc;
 
@@ -40,7 +40,7 @@ pub fn run() -> Result<()> {
 
         Some("start") => {
             let exe = std::env::current_exe()?;
-            let mut child = Command::new(exe).arg("--daemon").spawn()?;
+            let child = Command::new(exe).arg("--daemon").spawn()?;
             println!("touchctl: started daemon (pid={})", child.id());
             Ok(())
         }
@@ -57,6 +57,12 @@ pub fn run() -> Result<()> {
             Ok(())
         }
 
+        Some(op @ ("enable" | "disable" | "toggle")) => {
+            let r = ipc::client_request(serde_json::json!({ "op": op }))?;
+            print_response(&r);
+            Ok(())
+        }
+
         Some("reload") => {
             let r = ipc::client_request(serde_json::json!({"op":"reload"}))?;
             print_response(&r);
@@ -64,10 +70,15 @@ pub fn run() -> Result<()> {
         }
 
         Some("use") => {
+            let device: Option<String> = pargs.opt_value_from_str("--device")?;
             let name: String = pargs
                 .free_from_str()
-                .map_err(|_| anyhow!("usage: touchctl use <profile_name>"))?;
-            let r = ipc::client_request(serde_json::json!({"op":"use","profile":name}))?;
+                .map_err(|_| anyhow!("usage: touchctl use <profile_name> [--device <match>]"))?;
+            let mut req = serde_json::json!({"op":"use","profile":name});
+            if let Some(d) = device {
+                req["device"] = d.into();
+            }
+            let r = ipc::client_request(req)?;
             print_response(&r);
             Ok(())
         }
@@ -88,25 +99,35 @@ pub fn run() -> Result<()> {
             // usage:
             //   touchctl emit click right
             //   touchctl emit scroll 3
+            //   touchctl emit scroll -2 horizontal
             //   touchctl emit key CTRL+EQUAL
             let what: String = pargs
                 .free_from_str()
                 .map_err(|_| anyhow!("usage: touchctl emit <click|scroll|key> ..."))?;
             let mut sink = crate::actions::UinputSink::new()?;
             match what.as_str() {
-                "click" => {
-                    let btn: String = pargs
-                        .free_from_str()
-                        .map_err(|_| anyhow!("usage: touchctl emit click <left|right|middle>"))?;
-                    sink.click_mouse(&btn)?;
-                    println!("ok: clicked {btn}");
-                }
+                "click" => match pargs.opt_free_from_str::<String>()? {
+                    Some(btn) => {
+                        sink.click_mouse(&btn)?;
+                        println!("ok: clicked {btn}");
+                    }
+                    None => {
+                        sink.click_right()?;
+                        println!("ok: clicked right");
+                    }
+                },
                 "scroll" => {
-                    let steps: i32 = pargs
-                        .free_from_str()
-                        .map_err(|_| anyhow!("usage: touchctl emit scroll <steps>"))?;
-                    sink.scroll_vertical(steps)?;
-                    println!("ok: scrolled vertical {steps}");
+                    const USAGE: &str = "usage: touchctl emit scroll <steps> [vertical|horizontal]";
+                    let steps: i32 = pargs.free_from_str().map_err(|_| anyhow!(USAGE))?;
+                    let axis: String = pargs
+                        .opt_free_from_str()?
+                        .unwrap_or_else(|| "vertical".to_string());
+                    match axis.as_str() {
+                        "vertical" | "v" => sink.scroll_vertical(steps)?,
+                        "horizontal" | "h" => sink.scroll_horizontal(steps)?,
+                        _ => return Err(anyhow!(USAGE)),
+                    }
+                    println!("ok: scrolled {axis} {steps}");
                 }
                 "key" => {
                     let chord: String = pargs
@@ -120,6 +141,63 @@ pub fn run() -> Result<()> {
             Ok(())
         }
 
+        Some("record") => {
+            let device: Option<String> = pargs.opt_value_from_str("--device")?;
+            let out: String = pargs
+                .free_from_str()
+                .map_err(|_| anyhow!("usage: touchctl record <file> [--device <path>]"))?;
+            let n = crate::replay::record(Path::new(&out), device.as_deref())?;
+            println!("ok: recorded {n} events to {out}");
+            Ok(())
+        }
+
+        Some("replay") => {
+            let prof_path: Option<String> = pargs.opt_value_from_str("--profile")?;
+            let phases = pargs.contains("--phases");
+            let file: String = pargs.free_from_str().map_err(|_| {
+                anyhow!("usage: touchctl replay <file> [--profile <path>] [--phases]")
+            })?;
+            let profile = match prof_path {
+                Some(p) => crate::config::load_profile_file(Path::new(&p))?,
+                None => crate::config::builtin_profile()?,
+            };
+            let out = crate::replay::replay_file(Path::new(&file), profile.thresholds.clone())?;
+            let mut lines: Vec<(u128, String)> = out
+                .hits
+                .iter()
+                .map(|h| {
+                    let key = h.gesture.binding_key();
+                    let action = profile.bindings.get(key).map(String::as_str).unwrap_or("-");
+                    (h.t_ms, format!("{key:<24} {action}"))
+                })
+                .collect();
+            if phases {
+                lines.extend(out.phases.iter().map(|p| {
+                    let (d, t) = (&p.event.delta, &p.event.total);
+                    let line = format!(
+                        "{:<24} d=({:+.3},{:+.3}) x{:.3} {:+.1}°  total=({:+.3},{:+.3}) x{:.3} {:+.1}°",
+                        p.event.binding_key(),
+                        d.dx,
+                        d.dy,
+                        d.scale,
+                        d.rotation,
+                        t.dx,
+                        t.dy,
+                        t.scale,
+                        t.rotation
+                    );
+                    (p.t_ms, line)
+                }));
+                // stable: a frame's gesture stays ahead of its phases
+                lines.sort_by_key(|(t, _)| *t);
+            }
+            for (t, line) in &lines {
+                println!("{t:>8} ms  {line}");
+            }
+            println!("ok: {} gestures", out.hits.len());
+            Ok(())
+        }
+
         Some(other) => {
             eprintln!("unknown subcommand: {other}\n");
             print_help();
@@ -142,18 +220,23 @@ USAGE:
   touchctl start                          Start the daemon
   touchctl stop                           Stop the daemon
   touchctl status                         Show daemon state
+  touchctl enable | disable | toggle      Turn gesture output on/off
   touchctl reload                         Reload active profile
-  touchctl use <name>                     Switch active profile
+  touchctl use <name> [--device <m>]      Switch active profile, or one device's
   touchctl list                           List profiles
   touchctl doctor                         Diagnose permissions/devices
   touchctl emit click <left|right|middle> Emit a mouse click
-  touchctl emit scroll <steps>            Emit vertical scroll (+/- steps)
+  touchctl emit scroll <steps> [h|v]      Emit scroll (+/- steps, default vertical)
   touchctl emit key CTRL+EQUAL            Emit a key or chord
+  touchctl record <file> [--device <p>]   Record raw touch events to a file
+  touchctl replay <file> [--profile <p>] [--phases]
+                                          Replay a recording and print gestures
 
 TIPS:
   - Install systemd user unit: ~/.config/systemd/user/touchctl.service
   - Profiles: ~/.config/touchctl/profiles
   - Active profile pointer: ~/.config/touchctl/active
+  - Per-device profiles: ~/.config/touchctl/assignments.toml
 "#
     );
 }
@@ -163,22 +246,33 @@ fn print_subcmd_help(cmd: &str) {
         "start" => println!("usage: touchctl start\nStarts the background daemon."),
         "stop" => println!("usage: touchctl stop\nStops the running daemon."),
         "status" => println!(
-            "usage: touchctl status\nShows enabled flag, active profile, devices, socket, PID."
+            "usage: touchctl status\nShows enabled flag, active profile, open devices, recent hotplug events,\nsocket, PID, why the last profile reload failed (profile_error), and how\noften the kernel dropped events (syn_dropped, total and per device)."
+        ),
+        "enable" | "disable" | "toggle" => println!(
+            "usage: touchctl {cmd}\nTurns gesture output on/off without stopping the daemon (same as the 'toggle' action)."
         ),
         "reload" => println!(
-            "usage: touchctl reload\nReloads the current profile; keeps last good on error."
+            "usage: touchctl reload\nReloads the current profile; keeps last good on error.\nThe daemon also reloads on its own when profiles/ or the active pointer change."
         ),
         "use" => {
-            println!("usage: touchctl use <name>\nSwitches active profile to <name> and reloads.")
+            println!(
+                "usage: touchctl use <name> [--device <match>]\nSwitches active profile to <name> and reloads.\nWith --device, only devices matching <match> (vvvv:pppp vendor:product ID\nor a name glob like '*Touchscreen*') use <name>; others keep the active\nprofile. Assignments are kept in ~/.config/touchctl/assignments.toml."
+            )
         }
         "list" => {
             println!("usage: touchctl list\nLists available profiles; marks active with '*'.")
         }
         "doctor" => println!(
-            "usage: touchctl doctor\nChecks permissions and lists detected multitouch devices."
+            "usage: touchctl doctor\nChecks permissions and lists detected multitouch devices, whether each is\ncaptured, and which [devices] rule decided it."
         ),
         "emit" => println!(
-            "usage:\n  touchctl emit click <left|right|middle>\n  touchctl emit scroll <steps>\n  touchctl emit key CTRL+EQUAL"
+            "usage:\n  touchctl emit click [left|right|middle]   (default: right)\n  touchctl emit scroll <steps> [vertical|horizontal]\n  touchctl emit key CTRL+EQUAL"
+        ),
+        "record" => println!(
+            "usage: touchctl record <file> [--device /dev/input/eventN]\nRecords raw multitouch events until Ctrl-C (default: first multitouch device)."
+        ),
+        "replay" => println!(
+            "usage: touchctl replay <file> [--profile <path.toml>] [--phases]\nFeeds a recording through the tracker and gesture detector and prints the gestures (default: built-in profile). --phases also prints the begin/update/end phases of continuous gestures."
         ),
         _ => {
             eprintln!("unknown command: {cmd}\n");
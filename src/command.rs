@@ -0,0 +1,342 @@
+//! `cmd:` action runner.
+//!
+//! Commands are spawned off the gesture thread, run with a cleared
+//! environment (plus a whitelist), get a timeout, and have their output
+//! forwarded to the daemon log line by line. Each command runs in its own
+//! process group, and a timeout kills the whole group, so anything started
+//! by a `sh:` script goes with it. Commands get the gesture's source device
+//! as `TOUCHCTL_DEVICE`/`TOUCHCTL_DEVICE_NAME`; those bound to a phase of a
+//! continuous gesture also get the phase as `TOUCHCTL_*` variables.
+//!
+//! Binding syntax:
+//!   `cmd:notify-send "hello world"`  argv split, no shell
+//!   `cmd:sh:pactl set-sink-mute @DEFAULT_SINK@ toggle && ...`  via `sh -c`
+
+use anyhow::{Result, anyhow};
+use log::{error, info, warn};
+use std::{
+    io::{BufRead, BufReader, Read},
+    os::unix::process::CommandExt,
+    process::{Command, Stdio},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::config::CommandSettings;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandSpec {
+    Argv(Vec<String>),
+    Shell(String),
+}
+
+impl CommandSpec {
+    /// Parse the part of a binding after `cmd:`.
+    pub fn parse(rest: &str) -> Result<Self> {
+        let rest = rest.trim();
+        if let Some(script) = rest.strip_prefix("sh:") {
+            let script = script.trim();
+            if script.is_empty() {
+                return Err(anyhow!("empty shell command"));
+            }
+            return Ok(Self::Shell(script.to_string()));
+        }
+        let argv = split_argv(rest)?;
+        if argv.is_empty() {
+            return Err(anyhow!("empty command"));
+        }
+        Ok(Self::Argv(argv))
+    }
+
+    fn to_command(&self) -> Command {
+        match self {
+            Self::Argv(argv) => {
+                let mut c = Command::new(&argv[0]);
+                c.args(&argv[1..]);
+                c
+            }
+            Self::Shell(script) => {
+                let mut c = Command::new("sh");
+                c.arg("-c").arg(script);
+                c
+            }
+        }
+    }
+}
+
+/// Split a command line into argv without a shell. Supports single quotes,
+/// double quotes (with `\"` and `\\`), and backslash escapes outside quotes.
+pub fn split_argv(s: &str) -> Result<Vec<String>> {
+    let mut out = Vec::new();
+    let mut cur = String::new();
+    let mut in_word = false;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_word {
+                    out.push(std::mem::take(&mut cur));
+                    in_word = false;
+                }
+            }
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => cur.push(c),
+                        None => return Err(anyhow!("unterminated single quote")),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => cur.push(c),
+                            Some(c) => {
+                                cur.push('\\');
+                                cur.push(c);
+                            }
+                            None => return Err(anyhow!("unterminated double quote")),
+                        },
+                        Some(c) => cur.push(c),
+                        None => return Err(anyhow!("unterminated double quote")),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                match chars.next() {
+                    Some(c) => cur.push(c),
+                    None => return Err(anyhow!("trailing backslash")),
+                }
+            }
+            c => {
+                in_word = true;
+                cur.push(c);
+            }
+        }
+    }
+    if in_word {
+        out.push(cur);
+    }
+    Ok(out)
+}
+
+/// Spawns commands without blocking the caller and caps how many run at once.
+#[derive(Debug, Default, Clone)]
+pub struct CommandRunner {
+    running: Arc<AtomicUsize>,
+}
+
+impl CommandRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start `spec` on a worker thread with `env` added to its environment.
+    /// `tag` prefixes the log lines.
+    pub fn spawn(
+        &self,
+        tag: &str,
+        spec: CommandSpec,
+        env: Vec<(String, String)>,
+        settings: &CommandSettings,
+    ) -> Result<()> {
+        let n = self.running.fetch_add(1, Ordering::SeqCst);
+        if n >= settings.max_running {
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            return Err(anyhow!(
+                "{} commands already running; dropping '{tag}'",
+                settings.max_running
+            ));
+        }
+
+        let running = self.running.clone();
+        let tag = tag.to_string();
+        let settings = settings.clone();
+        let spawned = thread::Builder::new()
+            .name(format!("cmd {tag}"))
+            .spawn(move || {
+                if let Err(e) = run_to_completion(&tag, &spec, &env, &settings) {
+                    error!("[cmd {tag}] {e}");
+                }
+                running.fetch_sub(1, Ordering::SeqCst);
+            });
+        if let Err(e) = spawned {
+            // the thread never ran, so nothing else will free its slot
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+fn run_to_completion(
+    tag: &str,
+    spec: &CommandSpec,
+    env: &[(String, String)],
+    settings: &CommandSettings,
+) -> Result<()> {
+    let mut cmd = spec.to_command();
+    cmd.env_clear()
+        .process_group(0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    for key in &settings.env {
+        if let Some(v) = std::env::var_os(key) {
+            cmd.env(key, v);
+        }
+    }
+    cmd.envs(env.iter().map(|(k, v)| (k, v)));
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| anyhow!("failed to spawn {spec:?}: {e}"))?;
+    info!("[cmd {tag}] started pid={}", child.id());
+
+    // forwarders end on their own when the pipes close; not joined, so a
+    // backgrounded grandchild holding a pipe can't wedge this thread
+    if let Some(s) = child.stdout.take() {
+        forward(tag, "stdout", s);
+    }
+    if let Some(s) = child.stderr.take() {
+        forward(tag, "stderr", s);
+    }
+
+    let deadline = Instant::now() + Duration::from_millis(settings.timeout_ms);
+    let status = loop {
+        if let Some(st) = child.try_wait()? {
+            break st;
+        }
+        if Instant::now() >= deadline {
+            warn!(
+                "[cmd {tag}] timed out after {} ms; killing process group {}",
+                settings.timeout_ms,
+                child.id()
+            );
+            // the child leads its own group (process_group(0) above)
+            unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+            break child.wait()?;
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+
+    if status.success() {
+        info!("[cmd {tag}] exited: {status}");
+    } else {
+        warn!("[cmd {tag}] exited: {status}");
+    }
+    Ok(())
+}
+
+fn forward<R: Read + Send + 'static>(tag: &str, stream: &'static str, r: R) {
+    let tag = tag.to_string();
+    thread::spawn(move || {
+        for line in BufReader::new(r).lines().map_while(|l| l.ok()) {
+            if stream == "stderr" {
+                warn!("[cmd {tag}] {stream}: {line}");
+            } else {
+                info!("[cmd {tag}] {stream}: {line}");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(s: &str) -> Vec<String> {
+        split_argv(s).unwrap()
+    }
+
+    #[test]
+    fn splits_plain_words() {
+        assert_eq!(
+            argv("  notify-send  hi there "),
+            ["notify-send", "hi", "there"]
+        );
+    }
+
+    #[test]
+    fn honours_quotes_and_escapes() {
+        assert_eq!(
+            argv(r#"echo "a b" 'c "d"' e\ f "x\"y" ''"#),
+            ["echo", "a b", r#"c "d""#, "e f", r#"x"y"#, ""]
+        );
+    }
+
+    #[test]
+    fn shell_metacharacters_are_literal() {
+        assert_eq!(
+            argv("echo $HOME && rm -rf x"),
+            ["echo", "$HOME", "&&", "rm", "-rf", "x"]
+        );
+    }
+
+    #[test]
+    fn rejects_unterminated_quotes() {
+        assert!(split_argv("echo 'oops").is_err());
+        assert!(split_argv("echo \"oops").is_err());
+        assert!(split_argv("echo oops\\").is_err());
+    }
+
+    #[test]
+    fn parses_shell_and_argv_specs() {
+        assert_eq!(
+            CommandSpec::parse("sh: echo hi | wc -c").unwrap(),
+            CommandSpec::Shell("echo hi | wc -c".into())
+        );
+        assert_eq!(
+            CommandSpec::parse("xdotool key super").unwrap(),
+            CommandSpec::Argv(vec!["xdotool".into(), "key".into(), "super".into()])
+        );
+        assert!(CommandSpec::parse("   ").is_err());
+        assert!(CommandSpec::parse("sh:").is_err());
+    }
+
+    /// Whether `pid` is still running; a killed process waiting to be reaped
+    /// doesn't count.
+    fn running(pid: &str) -> bool {
+        std::fs::read_to_string(format!("/proc/{pid}/stat")).is_ok_and(|stat| {
+            !stat
+                .rsplit(')')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .starts_with('Z')
+        })
+    }
+
+    #[test]
+    fn timeout_kills_background_children() {
+        let pid_file =
+            std::env::temp_dir().join(format!("touchctl-cmd-test-{}", std::process::id()));
+        let spec = CommandSpec::Shell(format!("sleep 30 & echo $! > {}; wait", pid_file.display()));
+        let settings = CommandSettings {
+            timeout_ms: 300,
+            ..CommandSettings::default()
+        };
+        run_to_completion("test", &spec, &[], &settings).unwrap();
+
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        let _ = std::fs::remove_file(&pid_file);
+        let pid = pid.trim();
+        // the orphaned sleep is reaped by init, not by us
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while running(pid) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(!running(pid), "sleep {pid} survived the timeout");
+    }
+}
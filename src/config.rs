@@ -1,22 +1,26 @@
 use anyhow::{Result, anyhow};
 use directories::UserDirs;
 use log::{info, warn};
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs,
     io::Write,
     path::{Path, PathBuf},
 };
 
-#[derive(Debug, Clone, Deserialize)]
+use crate::command::CommandSpec;
+use crate::input::{self, DeviceInfo};
+use crate::tracker::{PalmFilter, Zone, ZoneFilter};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Meta {
     pub name: Option<String>,
     #[serde(default)]
     pub allow_commands: bool,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Thresholds {
     pub tap_ms: u64,
     pub hold_ms: u64,
@@ -25,13 +29,327 @@ pub struct Thresholds {
     pub swipe_max_ms: u64,
     pub pinch_sensitivity: f32,
     pub pinch_step: f32,
+    /// Degrees of turn per `rotate.cw` / `rotate.ccw`.
+    #[serde(default = "default_rotate_step_deg")]
+    pub rotate_step_deg: f32,
     pub smooth_ema: f32,
+    /// Exponential decay rate (1/s) of inertial scrolling after lift.
+    #[serde(default = "default_kinetic_friction")]
+    pub kinetic_friction: f32,
+    /// Release speed (normalized units/s) needed to start coasting; coasting
+    /// stops when it decays below this.
+    #[serde(default = "default_kinetic_min_velocity")]
+    pub kinetic_min_velocity: f32,
+    /// Unit of the distance thresholds (`move_tol`, `swipe_min_dist`,
+    /// `pinch_step`, `kinetic_min_velocity`, `palm_max_size`).
+    #[serde(default)]
+    pub distance_unit: DistanceUnit,
+    /// Leave palms out of finger counts: contacts the device marks as
+    /// MT_TOOL_PALM, and ones over the limits below (0 turns a limit off).
+    #[serde(default = "default_palm_rejection")]
+    pub palm_rejection: bool,
+    /// Largest touch (ABS_MT_TOUCH_MAJOR) still counted as a finger.
+    #[serde(default)]
+    pub palm_max_size: f32,
+    /// Largest pressure, as a fraction of the device's range, still counted.
+    #[serde(default)]
+    pub palm_max_pressure: f32,
+    /// Touches that start in these areas are ignored until they move out.
+    #[serde(default)]
+    pub edge_zones: Vec<Zone>,
+    /// A touch resting here doesn't count as a finger until it has moved
+    /// `move_tol`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumb_zone: Option<Zone>,
+    /// Length of a normalized unit of y in normalized units of x. Distances
+    /// are measured in units of x with y scaled by this, so `mm` thresholds
+    /// mean the same along either axis; set by
+    /// [`for_surface`](Self::for_surface).
+    #[serde(skip, default = "default_y_scale")]
+    pub y_scale: f32,
+}
+
+/// `norm`: fractions of the touchpad; `mm`: millimetres, converted per device
+/// from its reported resolution so gestures feel the same on any pad size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DistanceUnit {
+    #[default]
+    #[serde(alias = "normalized")]
+    Norm,
+    Mm,
+}
+
+/// Pad size assumed for `mm` thresholds when a device doesn't report its
+/// resolution.
+pub const NOMINAL_PAD_MM: (f32, f32) = (100.0, 65.0);
+
+impl Thresholds {
+    /// These thresholds in normalized units for a surface of `size_mm`.
+    /// `norm` thresholds come back unchanged.
+    pub fn for_surface(&self, size_mm: Option<(f32, f32)>) -> Thresholds {
+        let mut th = self.clone();
+        if self.distance_unit == DistanceUnit::Mm {
+            let (w, h) = size_mm.unwrap_or(NOMINAL_PAD_MM);
+            th.move_tol /= w;
+            th.swipe_min_dist /= w;
+            th.pinch_step /= w;
+            th.kinetic_min_velocity /= w;
+            th.y_scale = h / w;
+            // touch sizes are normalized by the mean side length
+            th.palm_max_size /= (w + h) / 2.0;
+            th.distance_unit = DistanceUnit::Norm;
+        }
+        th
+    }
+
+    /// Length of a move by `(dx, dy)` normalized units, in units of x.
+    pub fn dist(&self, dx: f32, dy: f32) -> f32 {
+        dx.hypot(dy * self.y_scale)
+    }
+
+    /// Span change per pinch step: `pinch_step`, divided by
+    /// `pinch_sensitivity` so a higher sensitivity zooms more per pinch.
+    pub fn pinch_span_step(&self) -> f32 {
+        self.pinch_step / self.pinch_sensitivity
+    }
+
+    /// The tracker's palm filter; call on thresholds resolved by
+    /// [`for_surface`](Self::for_surface).
+    pub fn palm_filter(&self) -> PalmFilter {
+        PalmFilter {
+            enabled: self.palm_rejection,
+            max_size: self.palm_max_size,
+            max_pressure: self.palm_max_pressure,
+        }
+    }
+
+    /// The tracker's edge and thumb zones; call on thresholds resolved by
+    /// [`for_surface`](Self::for_surface).
+    pub fn zone_filter(&self) -> ZoneFilter {
+        ZoneFilter {
+            edges: self.edge_zones.clone(),
+            thumb: self.thumb_zone,
+            thumb_move: self.move_tol,
+        }
+    }
+}
+
+fn default_kinetic_friction() -> f32 {
+    4.0
+}
+
+fn default_y_scale() -> f32 {
+    1.0
+}
+
+fn default_kinetic_min_velocity() -> f32 {
+    0.3
+}
+
+fn default_palm_rejection() -> bool {
+    true
+}
+
+fn default_rotate_step_deg() -> f32 {
+    15.0
+}
+
+/// Which wheel axes the virtual device advertises and emits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WheelMode {
+    /// REL_WHEEL / REL_HWHEEL only, whole detents.
+    Legacy,
+    /// REL_WHEEL_HI_RES / REL_HWHEEL_HI_RES only, 120 units per detent.
+    #[serde(alias = "hi-res")]
+    Hires,
+    /// Both, as a hi-res mouse would report them.
+    #[default]
+    Both,
+}
+
+impl WheelMode {
+    pub fn legacy(self) -> bool {
+        matches!(self, Self::Legacy | Self::Both)
+    }
+    pub fn hires(self) -> bool {
+        matches!(self, Self::Hires | Self::Both)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScrollSettings {
+    pub wheel: WheelMode,
+}
+
+/// Limits for `cmd:` actions (only used when `meta.allow_commands = true`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandSettings {
+    pub timeout_ms: u64,
+    pub max_running: usize,
+    /// Environment variables passed through; everything else is cleared.
+    pub env: Vec<String>,
+}
+
+impl Default for CommandSettings {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 5000,
+            max_running: 4,
+            env: [
+                "PATH",
+                "HOME",
+                "USER",
+                "LANG",
+                "DISPLAY",
+                "WAYLAND_DISPLAY",
+                "XDG_RUNTIME_DIR",
+                "DBUS_SESSION_BUS_ADDRESS",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+        }
+    }
+}
+
+/// Which multitouch devices the daemon captures (`[devices]`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceRules {
+    /// Applies to devices no rule matches.
+    pub default: RuleAction,
+    /// Checked in order; the first rule whose given fields all match wins.
+    pub rules: Vec<DeviceRule>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    #[default]
+    Include,
+    Exclude,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceRule {
+    pub action: RuleAction,
+    /// Glob (`*`, `?`) on the device name, case-insensitive.
+    pub name: Option<String>,
+    pub vendor: Option<u16>,
+    pub product: Option<u16>,
+    /// `usb`, `bluetooth`, `i2c`, `i8042`, ...
+    pub bus: Option<String>,
+    /// Glob on the device node, e.g. `/dev/input/event1*`.
+    pub path: Option<String>,
+}
+
+/// Outcome of [`DeviceRules::evaluate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceVerdict {
+    pub action: RuleAction,
+    /// Index into `rules`; `None` when the default applied.
+    pub rule: Option<usize>,
+}
+
+impl DeviceVerdict {
+    pub fn included(&self) -> bool {
+        self.action == RuleAction::Include
+    }
+}
+
+impl DeviceRules {
+    pub fn evaluate(&self, dev: &DeviceInfo) -> DeviceVerdict {
+        for (i, r) in self.rules.iter().enumerate() {
+            if r.matches(dev) {
+                return DeviceVerdict {
+                    action: r.action,
+                    rule: Some(i),
+                };
+            }
+        }
+        DeviceVerdict {
+            action: self.default,
+            rule: None,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+impl DeviceRule {
+    fn matches(&self, dev: &DeviceInfo) -> bool {
+        self.name
+            .as_ref()
+            .is_none_or(|g| glob_match(&g.to_ascii_lowercase(), &dev.name.to_ascii_lowercase()))
+            && self.vendor.is_none_or(|v| v == dev.vendor)
+            && self.product.is_none_or(|p| p == dev.product)
+            && self
+                .bus
+                .as_ref()
+                .is_none_or(|b| b.eq_ignore_ascii_case(input::bus_name(dev.bus)))
+            && self.path.as_ref().is_none_or(|g| glob_match(g, &dev.path))
+    }
+
+    /// e.g. `exclude name=*Wacom* bus=usb`
+    pub fn describe(&self) -> String {
+        let mut s = format!("{:?}", self.action).to_ascii_lowercase();
+        if let Some(n) = &self.name {
+            s += &format!(" name={n}");
+        }
+        if let Some(v) = self.vendor {
+            s += &format!(" vendor={v:#06x}");
+        }
+        if let Some(p) = self.product {
+            s += &format!(" product={p:#06x}");
+        }
+        if let Some(b) = &self.bus {
+            s += &format!(" bus={b}");
+        }
+        if let Some(p) = &self.path {
+            s += &format!(" path={p}");
+        }
+        s
+    }
+}
+
+/// Shell-style glob supporting `*` (any run) and `?` (one char).
+fn glob_match(pat: &str, s: &str) -> bool {
+    let p: Vec<char> = pat.chars().collect();
+    let t: Vec<char> = s.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    // last `*` seen and the text position it is currently absorbing up to
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Profile {
     pub meta: Meta,
     pub thresholds: Thresholds,
+    #[serde(default)]
+    pub scroll: ScrollSettings,
+    #[serde(default)]
+    pub commands: CommandSettings,
+    #[serde(default)]
+    pub devices: DeviceRules,
 
     // 🔧 Accept nested/dotted tables and flatten them into "a.b" -> "value"
     #[serde(deserialize_with = "deserialize_bindings_flat")]
@@ -92,14 +410,78 @@ fn flatten_table(
 }
 // ------------------------------------------------------------
 
+/// A device that uses its own profile instead of the active one
+/// (`touchctl use <profile> --device <match>`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Assignment {
+    /// `vvvv:pppp` (hex vendor:product ID) or a device name glob.
+    #[serde(rename = "match")]
+    pub matcher: String,
+    pub profile: String,
+}
+
+impl Assignment {
+    pub fn matches(&self, dev: &DeviceInfo) -> bool {
+        match parse_vid_pid(&self.matcher) {
+            Some((v, p)) => dev.vendor == v && dev.product == p,
+            None => glob_match(
+                &self.matcher.to_ascii_lowercase(),
+                &dev.name.to_ascii_lowercase(),
+            ),
+        }
+    }
+}
+
+fn parse_vid_pid(s: &str) -> Option<(u16, u16)> {
+    let (v, p) = s.split_once(':')?;
+    if v.len() != 4 || p.len() != 4 {
+        return None;
+    }
+    Some((
+        u16::from_str_radix(v, 16).ok()?,
+        u16::from_str_radix(p, 16).ok()?,
+    ))
+}
+
+/// On-disk form of `assignments.toml`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AssignmentsFile {
+    #[serde(default)]
+    assign: Vec<Assignment>,
+}
+
+/// The active profile plus per-device assignments, as the pipeline sees them.
+#[derive(Debug, Clone)]
+pub struct ProfileSet {
+    pub active_name: String,
+    pub global: Profile,
+    /// In file order; the first assignment matching a device wins.
+    pub assigned: Vec<(Assignment, Profile)>,
+}
+
+impl ProfileSet {
+    /// The profile `dev` uses and its name.
+    pub fn for_device(&self, dev: &DeviceInfo) -> (&str, &Profile) {
+        self.assigned
+            .iter()
+            .find(|(a, _)| a.matches(dev))
+            .map(|(a, p)| (a.profile.as_str(), p))
+            .unwrap_or((self.active_name.as_str(), &self.global))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DaemonConfigState {
     pub active_name: String,
     pub profile: Profile,
+    /// Per-device overrides with their loaded profiles.
+    pub assignments: Vec<(Assignment, Profile)>,
     pub config_dir: PathBuf,
     pub profiles_dir: PathBuf,
     pub active_ptr: PathBuf,
-    pub detected_devices: Vec<String>,
+    pub assignments_path: PathBuf,
+    /// Why the last reload failed; cleared by the next successful one.
+    pub last_error: Option<String>,
 }
 
 fn config_dir() -> PathBuf {
@@ -115,6 +497,10 @@ fn active_ptr_path() -> PathBuf {
     config_dir().join("active")
 }
 
+fn assignments_path() -> PathBuf {
+    config_dir().join("assignments.toml")
+}
+
 fn default_profile_text() -> &'static str {
     include_str!("../profiles/default.toml")
 }
@@ -138,32 +524,94 @@ impl DaemonConfigState {
         }
 
         let active_name = fs::read_to_string(&active_ptr)?.trim().to_string();
-        let profile = Self::load_profile(&active_name)?;
-        let detected_devices = detect_multitouch_devices();
+        let profile = load_named_profile(&profdir, &active_name)?;
+
+        // a bad assignment shouldn't keep the daemon from starting
+        let assignments_path = assignments_path();
+        let (assignments, last_error) = match load_assignments(&assignments_path, &profdir) {
+            Ok(a) => (a, None),
+            Err(e) => {
+                warn!("ignoring device assignments: {e}");
+                (vec![], Some(e.to_string()))
+            }
+        };
 
         Ok(Self {
             active_name,
             profile,
+            assignments,
             config_dir: cfgdir,
             profiles_dir: profdir,
             active_ptr,
-            detected_devices,
+            assignments_path,
+            last_error,
         })
     }
 
+    /// Reload the active profile and every assigned one; all or nothing.
     pub fn reload(&mut self) -> Result<()> {
-        self.profile = Self::load_profile(&self.active_name)?;
+        let profile = load_named_profile(&self.profiles_dir, &self.active_name)?;
+        self.assignments = load_assignments(&self.assignments_path, &self.profiles_dir)?;
+        self.profile = profile;
+        Ok(())
+    }
+
+    /// Re-read the `active` pointer and load the profile it names. On error
+    /// the current profile (and name) stay in place.
+    pub fn sync_with_disk(&mut self) -> Result<()> {
+        let name = fs::read_to_string(&self.active_ptr)
+            .map_err(|e| anyhow!("failed to read {}: {e}", self.active_ptr.display()))?
+            .trim()
+            .to_string();
+        if name.is_empty() {
+            return Err(anyhow!("{} is empty", self.active_ptr.display()));
+        }
+        let profile = load_named_profile(&self.profiles_dir, &name)?;
+        self.assignments = load_assignments(&self.assignments_path, &self.profiles_dir)?;
+        self.profile = profile;
+        self.active_name = name;
         Ok(())
     }
 
+    /// Make devices matching `matcher` use profile `name`, replacing any
+    /// earlier assignment for the same matcher, and persist it.
+    pub fn assign(&mut self, name: &str, matcher: &str) -> Result<()> {
+        let matcher = matcher.trim();
+        if matcher.is_empty() {
+            return Err(anyhow!("empty device match"));
+        }
+        let profile = load_named_profile(&self.profiles_dir, name)?;
+        let a = Assignment {
+            matcher: matcher.to_string(),
+            profile: name.to_string(),
+        };
+        let mut next = self.assignments.clone();
+        match next.iter_mut().find(|(x, _)| x.matcher == a.matcher) {
+            Some(slot) => *slot = (a, profile),
+            None => next.push((a, profile)),
+        }
+        save_assignments(&self.assignments_path, &next)?;
+        self.assignments = next;
+        Ok(())
+    }
+
+    pub fn profile_set(&self) -> ProfileSet {
+        ProfileSet {
+            active_name: self.active_name.clone(),
+            global: self.profile.clone(),
+            assigned: self.assignments.clone(),
+        }
+    }
+
     pub fn set_active(&mut self, name: &str) -> Result<()> {
         let p = self.profiles_dir.join(format!("{name}.toml"));
         if !p.exists() {
             return Err(anyhow!("profile not found: {}", p.display()));
         }
+        let profile = load_named_profile(&self.profiles_dir, name)?;
         fs::write(&self.active_ptr, name.as_bytes())?;
         self.active_name = name.to_string();
-        self.reload()?;
+        self.profile = profile;
         Ok(())
     }
 
@@ -171,12 +619,11 @@ impl DaemonConfigState {
         let mut v = Vec::new();
         if let Ok(rd) = fs::read_dir(&self.profiles_dir) {
             for e in rd.flatten() {
-                if let Some(ext) = e.path().extension() {
-                    if ext == "toml" {
-                        if let Some(stem) = e.path().file_stem().and_then(|s| s.to_str()) {
-                            v.push(stem.to_string());
-                        }
-                    }
+                if let Some(ext) = e.path().extension()
+                    && ext == "toml"
+                    && let Some(stem) = e.path().file_stem().and_then(|s| s.to_str())
+                {
+                    v.push(stem.to_string());
                 }
             }
         }
@@ -184,25 +631,37 @@ impl DaemonConfigState {
         v
     }
 
-    fn load_profile(name: &str) -> Result<Profile> {
-        let path = profiles_dir().join(format!("{name}.toml"));
-        let txt = fs::read_to_string(&path)
-            .map_err(|e| anyhow!("failed to read {}: {e}", path.display()))?;
-        let profile: Profile =
-            toml::from_str(&txt).map_err(|e| anyhow!("failed to parse {}: {e}", path.display()))?;
-        validate_profile(&profile)?;
-        Ok(profile)
-    }
-
     pub fn doctor_report(&self) -> serde_json::Value {
         let uinput_ok = Path::new("/dev/uinput").exists();
         let in_input_group = check_in_input_group();
+        let rules = &self.profile.devices;
+        let set = self.profile_set();
+        let devices: Vec<_> = input::discover_multitouch()
+            .iter()
+            .map(|d| {
+                let v = rules.evaluate(d);
+                let matched = match v.rule {
+                    Some(i) => format!("rules[{i}]: {}", rules.rules[i].describe()),
+                    None => format!("default: {:?}", v.action).to_ascii_lowercase(),
+                };
+                serde_json::json!({
+                    "path": d.path,
+                    "name": d.name,
+                    "vendor": format!("{:04x}", d.vendor),
+                    "product": format!("{:04x}", d.product),
+                    "bus": input::bus_name(d.bus),
+                    "captured": v.included(),
+                    "matched": matched,
+                    "profile": set.for_device(d).0,
+                })
+            })
+            .collect();
         serde_json::json!({
             "uinput_present": uinput_ok,
             "input_group_member": in_input_group,
             "profiles_dir": self.profiles_dir,
             "active_profile": self.active_name,
-            "devices": self.detected_devices,
+            "devices": devices,
             "hints": {
                 "udev_rule": "/etc/udev/rules.d/80-uinput.rules",
                 "add_user_to_input_group": "sudo usermod -aG input $USER && newgrp input"
@@ -211,15 +670,151 @@ impl DaemonConfigState {
     }
 }
 
+/// Dotted paths of every setting that differs between two profiles,
+/// e.g. `thresholds.tap_ms` or `bindings.two_finger.tap`.
+pub fn profile_diff(old: &Profile, new: &Profile) -> Vec<String> {
+    fn flatten(prefix: &str, v: &serde_json::Value, out: &mut BTreeMap<String, serde_json::Value>) {
+        match v {
+            serde_json::Value::Object(m) if prefix != "bindings" => {
+                for (k, v) in m {
+                    let key = if prefix.is_empty() {
+                        k.clone()
+                    } else {
+                        format!("{prefix}.{k}")
+                    };
+                    flatten(&key, v, out);
+                }
+            }
+            serde_json::Value::Object(m) => {
+                // binding keys are already dotted; don't split them further
+                for (k, v) in m {
+                    out.insert(format!("{prefix}.{k}"), v.clone());
+                }
+            }
+            other => {
+                out.insert(prefix.to_string(), other.clone());
+            }
+        }
+    }
+
+    let mut a = BTreeMap::new();
+    let mut b = BTreeMap::new();
+    flatten("", &serde_json::to_value(old).unwrap_or_default(), &mut a);
+    flatten("", &serde_json::to_value(new).unwrap_or_default(), &mut b);
+
+    let mut keys: Vec<String> = a
+        .keys()
+        .chain(b.keys())
+        .filter(|k| a.get(*k) != b.get(*k))
+        .cloned()
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+fn load_assignments(path: &Path, profiles_dir: &Path) -> Result<Vec<(Assignment, Profile)>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let txt =
+        fs::read_to_string(path).map_err(|e| anyhow!("failed to read {}: {e}", path.display()))?;
+    let file: AssignmentsFile =
+        toml::from_str(&txt).map_err(|e| anyhow!("failed to parse {}: {e}", path.display()))?;
+    file.assign
+        .into_iter()
+        .map(|a| {
+            let p = load_named_profile(profiles_dir, &a.profile)
+                .map_err(|e| anyhow!("device '{}': {e}", a.matcher))?;
+            Ok((a, p))
+        })
+        .collect()
+}
+
+fn save_assignments(path: &Path, assignments: &[(Assignment, Profile)]) -> Result<()> {
+    let file = AssignmentsFile {
+        assign: assignments.iter().map(|(a, _)| a.clone()).collect(),
+    };
+    let txt = format!(
+        "# Per-device profiles, first match wins. Written by `touchctl use --device`.\n\n{}",
+        toml::to_string(&file)?
+    );
+    fs::write(path, txt).map_err(|e| anyhow!("failed to write {}: {e}", path.display()))
+}
+
+fn load_named_profile(profiles_dir: &Path, name: &str) -> Result<Profile> {
+    load_profile_file(&profiles_dir.join(format!("{name}.toml")))
+}
+
+/// Load and validate a profile from an arbitrary path.
+pub fn load_profile_file(path: &Path) -> Result<Profile> {
+    let txt =
+        fs::read_to_string(path).map_err(|e| anyhow!("failed to read {}: {e}", path.display()))?;
+    let profile: Profile =
+        toml::from_str(&txt).map_err(|e| anyhow!("failed to parse {}: {e}", path.display()))?;
+    validate_profile(&profile)?;
+    Ok(profile)
+}
+
+/// The profile shipped in the binary, independent of anything in ~/.config.
+pub fn builtin_profile() -> Result<Profile> {
+    let profile: Profile = toml::from_str(default_profile_text())
+        .map_err(|e| anyhow!("failed to parse built-in profile: {e}"))?;
+    validate_profile(&profile)?;
+    Ok(profile)
+}
+
 fn validate_profile(p: &Profile) -> Result<()> {
     if p.thresholds.tap_ms == 0 || p.thresholds.hold_ms == 0 {
         return Err(anyhow!("thresholds must be positive durations"));
     }
-    if !(0.0..1.0).contains(&p.thresholds.move_tol) {
+    match p.thresholds.distance_unit {
+        DistanceUnit::Norm if !(0.0..1.0).contains(&p.thresholds.move_tol) => {
+            return Err(anyhow!(
+                "thresholds.move_tol must be in (0,1) normalized units"
+            ));
+        }
+        DistanceUnit::Mm if p.thresholds.move_tol <= 0.0 => {
+            return Err(anyhow!("thresholds.move_tol must be > 0 mm"));
+        }
+        _ => {}
+    }
+
+    if p.thresholds.pinch_step <= 0.0 || p.thresholds.pinch_sensitivity <= 0.0 {
+        return Err(anyhow!(
+            "thresholds.pinch_step and pinch_sensitivity must be > 0"
+        ));
+    }
+
+    if !(p.thresholds.rotate_step_deg > 0.0 && p.thresholds.rotate_step_deg < 180.0) {
+        return Err(anyhow!(
+            "thresholds.rotate_step_deg must be in (0,180) degrees"
+        ));
+    }
+
+    if p.thresholds.kinetic_friction <= 0.0 || p.thresholds.kinetic_min_velocity < 0.0 {
+        return Err(anyhow!(
+            "thresholds.kinetic_friction must be > 0 and kinetic_min_velocity >= 0"
+        ));
+    }
+
+    if p.thresholds.palm_max_size < 0.0 || !(0.0..=1.0).contains(&p.thresholds.palm_max_pressure) {
         return Err(anyhow!(
-            "thresholds.move_tol must be in (0,1) normalized units"
+            "thresholds.palm_max_size must be >= 0 and palm_max_pressure in [0,1]"
         ));
     }
+    let zones = p.thresholds.edge_zones.iter().map(|z| ("edge_zones", z));
+    for (what, z) in zones.chain(p.thresholds.thumb_zone.iter().map(|z| ("thumb_zone", z))) {
+        let in_pad = |v: f32| (0.0..=1.0).contains(&v);
+        if !(in_pad(z.x0) && in_pad(z.x1) && in_pad(z.y0) && in_pad(z.y1))
+            || z.x0 >= z.x1
+            || z.y0 >= z.y1
+        {
+            return Err(anyhow!(
+                "thresholds.{what}: need 0 <= x0 < x1 <= 1 and 0 <= y0 < y1 <= 1"
+            ));
+        }
+    }
 
     for (k, v) in &p.bindings {
         if k.trim().is_empty() {
@@ -237,62 +832,255 @@ fn validate_profile(p: &Profile) -> Result<()> {
         if !ok {
             return Err(anyhow!("binding '{}' has invalid action '{}'", k, v));
         }
-        if v.starts_with("cmd:") && !p.meta.allow_commands {
+        if let Some(rest) = v.strip_prefix("cmd:") {
+            if !p.meta.allow_commands {
+                return Err(anyhow!(
+                    "binding '{}' uses cmd: but allow_commands=false",
+                    k
+                ));
+            }
+            CommandSpec::parse(rest).map_err(|e| anyhow!("binding '{}': {e}", k))?;
+        }
+    }
+    for (i, r) in p.devices.rules.iter().enumerate() {
+        if r.name.is_none()
+            && r.vendor.is_none()
+            && r.product.is_none()
+            && r.bus.is_none()
+            && r.path.is_none()
+        {
             return Err(anyhow!(
-                "binding '{}' uses cmd: but allow_commands=false",
-                k
+                "devices.rules[{i}] matches nothing; give name, vendor, product, bus or path"
             ));
         }
-    }
-    Ok(())
-}
-
-fn detect_multitouch_devices() -> Vec<String> {
-    use evdev::{AbsoluteAxisCode, Device, EventType};
-    let mut out = vec![];
-    if let Ok(rd) = fs::read_dir("/dev/input") {
-        for e in rd.flatten() {
-            let p = e.path();
-            if p.file_name()
-                .and_then(|s| s.to_str())
-                .map(|s| s.starts_with("event"))
-                .unwrap_or(false)
-            {
-                if let Ok(dev) = Device::open(&p) {
-                    let has_abs = dev.supported_events().contains(EventType::ABSOLUTE);
-                    let axes = dev.supported_absolute_axes();
-                    let has_mt = axes.map_or(false, |a| {
-                        a.contains(AbsoluteAxisCode::ABS_MT_SLOT)
-                            && a.contains(AbsoluteAxisCode::ABS_MT_POSITION_X)
-                            && a.contains(AbsoluteAxisCode::ABS_MT_POSITION_Y)
-                    });
-                    if has_abs && has_mt {
-                        let name = dev.name().unwrap_or("unknown").to_string();
-                        out.push(format!("{} ({})", name, p.display()));
-                    }
-                }
-            }
+        if let Some(b) = &r.bus
+            && !input::is_known_bus(b)
+        {
+            return Err(anyhow!("devices.rules[{i}]: unknown bus '{b}'"));
         }
     }
-    out
+    if p.commands.timeout_ms == 0 || p.commands.max_running == 0 {
+        return Err(anyhow!(
+            "commands.timeout_ms and commands.max_running must be positive"
+        ));
+    }
+    Ok(())
 }
 
 fn check_in_input_group() -> bool {
     if let Ok(s) = fs::read_to_string("/etc/group") {
         let user = whoami::username();
         for line in s.lines() {
-            if line.starts_with("input:") || line.starts_with("input:x:") {
-                if line
+            if (line.starts_with("input:") || line.starts_with("input:x:"))
+                && line
                     .split(':')
                     .nth(3)
                     .unwrap_or("")
                     .split(',')
                     .any(|u| u == user)
-                {
-                    return true;
-                }
+            {
+                return true;
             }
         }
     }
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_in(dir: &Path) -> DaemonConfigState {
+        let profiles_dir = dir.join("profiles");
+        fs::create_dir_all(&profiles_dir).unwrap();
+        fs::write(profiles_dir.join("default.toml"), default_profile_text()).unwrap();
+        let active_ptr = dir.join("active");
+        fs::write(&active_ptr, "default").unwrap();
+        DaemonConfigState {
+            active_name: "default".into(),
+            profile: builtin_profile().unwrap(),
+            assignments: vec![],
+            config_dir: dir.to_path_buf(),
+            profiles_dir,
+            active_ptr,
+            assignments_path: dir.join("assignments.toml"),
+            last_error: None,
+        }
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("touchctl-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn sync_with_disk_follows_pointer_and_keeps_last_good() {
+        let dir = scratch_dir("sync");
+        let mut st = state_in(&dir);
+
+        let edited = default_profile_text().replace("tap_ms = 200", "tap_ms = 150");
+        assert_ne!(edited, default_profile_text(), "default.toml changed");
+        fs::write(st.profiles_dir.join("work.toml"), edited).unwrap();
+        fs::write(&st.active_ptr, "work\n").unwrap();
+        st.sync_with_disk().unwrap();
+        assert_eq!(st.active_name, "work");
+        assert_eq!(st.profile.thresholds.tap_ms, 150);
+
+        fs::write(st.profiles_dir.join("work.toml"), "[meta\nbroken").unwrap();
+        assert!(st.sync_with_disk().is_err());
+        assert_eq!(st.active_name, "work");
+        assert_eq!(st.profile.thresholds.tap_ms, 150);
+
+        fs::write(&st.active_ptr, "missing").unwrap();
+        assert!(st.sync_with_disk().is_err());
+        assert_eq!(st.active_name, "work");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    fn device(name: &str, path: &str, vendor: u16, bus: u16) -> DeviceInfo {
+        DeviceInfo {
+            path: path.into(),
+            name: name.into(),
+            vendor,
+            product: 0x0001,
+            bus,
+        }
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("*wacom*", "wacom intuos pro finger"));
+        assert!(glob_match("/dev/input/event?", "/dev/input/event7"));
+        assert!(!glob_match("/dev/input/event?", "/dev/input/event17"));
+        assert!(glob_match("a*b*c", "aXXbYYbc"));
+        assert!(!glob_match("a*b", "ac"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn first_matching_device_rule_wins() {
+        let rules: DeviceRules = toml::from_str(
+            r#"
+            default = "exclude"
+            [[rules]]
+            action = "exclude"
+            name = "*Wacom*"
+            [[rules]]
+            action = "include"
+            bus = "i2c"
+            [[rules]]
+            action = "include"
+            vendor = 0x056a
+            "#,
+        )
+        .unwrap();
+
+        let tablet = device(
+            "Wacom Intuos Pro M Finger",
+            "/dev/input/event9",
+            0x056a,
+            0x03,
+        );
+        let pad = device(
+            "ELAN1200:00 04F3:3090 Touchpad",
+            "/dev/input/event5",
+            0x04f3,
+            0x18,
+        );
+        let screen = device("Touchscreen", "/dev/input/event6", 0x1234, 0x03);
+
+        let v = rules.evaluate(&tablet);
+        assert_eq!((v.included(), v.rule), (false, Some(0)));
+        let v = rules.evaluate(&pad);
+        assert_eq!((v.included(), v.rule), (true, Some(1)));
+        let v = rules.evaluate(&screen);
+        assert_eq!((v.included(), v.rule), (false, None));
+        assert_eq!(rules.rules[0].describe(), "exclude name=*Wacom*");
+    }
+
+    #[test]
+    fn devices_use_assigned_profiles_and_fall_back_to_active() {
+        let dir = scratch_dir("assign");
+        let mut st = state_in(&dir);
+        let screen = default_profile_text().replace("tap_ms = 200", "tap_ms = 120");
+        fs::write(st.profiles_dir.join("screen.toml"), screen).unwrap();
+
+        st.assign("screen", "*touchscreen*").unwrap();
+        st.assign("default", "04f3:3090").unwrap();
+        st.assign("screen", "04f3:3090").unwrap();
+        assert!(st.assign("missing", "*").is_err());
+        assert_eq!(st.assignments.len(), 2);
+
+        // persisted and reloaded
+        st.assignments.clear();
+        st.reload().unwrap();
+        let set = st.profile_set();
+        assert_eq!(set.assigned.len(), 2);
+
+        let mut pad = device(
+            "ELAN1200:00 04F3:3090 Touchpad",
+            "/dev/input/event5",
+            0x04f3,
+            0x18,
+        );
+        pad.product = 0x3090;
+        let screen = device("ELAN Touchscreen", "/dev/input/event6", 0x1234, 0x03);
+        let other = device("Synaptics TM3276", "/dev/input/event7", 0x06cb, 0x11);
+
+        let (name, p) = set.for_device(&screen);
+        assert_eq!((name, p.thresholds.tap_ms), ("screen", 120));
+        assert_eq!(set.for_device(&pad).0, "screen");
+        let (name, p) = set.for_device(&other);
+        assert_eq!((name, p.thresholds.tap_ms), ("default", 200));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn palm_limits_are_validated() {
+        let mut p = builtin_profile().unwrap();
+        p.thresholds.palm_max_size = 0.3;
+        p.thresholds.palm_max_pressure = 0.8;
+        assert!(validate_profile(&p).is_ok());
+
+        p.thresholds.palm_max_size = -0.1;
+        assert!(validate_profile(&p).is_err());
+        p.thresholds.palm_max_size = 0.3;
+        p.thresholds.palm_max_pressure = 1.5;
+        assert!(validate_profile(&p).is_err());
+    }
+
+    #[test]
+    fn mm_thresholds_scale_with_pad_size() {
+        let mut th = builtin_profile().unwrap().thresholds;
+        assert_eq!(th.for_surface(Some((50.0, 30.0))).move_tol, th.move_tol);
+
+        th.distance_unit = DistanceUnit::Mm;
+        th.swipe_min_dist = 8.0;
+        let small = th.for_surface(Some((60.0, 40.0)));
+        let large = th.for_surface(Some((150.0, 90.0)));
+        assert!((small.swipe_min_dist - 8.0 / 60.0).abs() < 1e-6);
+        assert!((large.swipe_min_dist - 8.0 / 150.0).abs() < 1e-6);
+        assert_eq!(small.distance_unit, DistanceUnit::Norm);
+    }
+
+    #[test]
+    fn mm_thresholds_hold_along_both_axes() {
+        let mut th = builtin_profile().unwrap().thresholds;
+        th.distance_unit = DistanceUnit::Mm;
+        th.swipe_min_dist = 8.0;
+        // 8 mm is 0.08 of the width but 0.16 of the height
+        let wide = th.for_surface(Some((100.0, 50.0)));
+        let across = wide.dist(0.08, 0.0);
+        let down = wide.dist(0.0, 0.16);
+        assert!((across - wide.swipe_min_dist).abs() < 1e-6, "{across}");
+        assert!((down - wide.swipe_min_dist).abs() < 1e-6, "{down}");
+        // 3-4-5 mm
+        assert!((wide.dist(0.03, 0.08) - 0.05).abs() < 1e-6);
+
+        th.distance_unit = DistanceUnit::Norm;
+        assert_eq!(th.for_surface(Some((100.0, 50.0))).dist(0.0, 0.1), 0.1);
+    }
+}
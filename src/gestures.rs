@@ -1,7 +1,7 @@
 use crate::config::Thresholds;
 use crate::tracker::FrameSummary;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Gesture {
     TwoFingerTap,
     TwoFingerSwipeUp,
@@ -10,22 +10,306 @@ pub enum Gesture {
     TwoFingerSwipeRight,
     PinchScaleIn,
     PinchScaleOut,
+    RotateClockwise,
+    RotateCounterClockwise,
     ThreeFingerTap,
+    ThreeFingerSwipeUp,
+    ThreeFingerSwipeDown,
+    ThreeFingerSwipeLeft,
+    ThreeFingerSwipeRight,
+    FourFingerSwipeUp,
+    FourFingerSwipeDown,
+    FourFingerSwipeLeft,
+    FourFingerSwipeRight,
+    OneFingerHold,
+    TwoFingerHold,
+    ThreeFingerHold,
+}
+
+impl Gesture {
+    /// Profile binding key this gesture is looked up under.
+    pub fn binding_key(&self) -> &'static str {
+        match self {
+            Gesture::TwoFingerTap => "two_finger.tap",
+            Gesture::TwoFingerSwipeUp => "two_finger.swipe_up",
+            Gesture::TwoFingerSwipeDown => "two_finger.swipe_down",
+            Gesture::TwoFingerSwipeLeft => "two_finger.swipe_left",
+            Gesture::TwoFingerSwipeRight => "two_finger.swipe_right",
+            Gesture::PinchScaleIn => "pinch.scale_in",
+            Gesture::PinchScaleOut => "pinch.scale_out",
+            Gesture::RotateClockwise => "rotate.cw",
+            Gesture::RotateCounterClockwise => "rotate.ccw",
+            Gesture::ThreeFingerTap => "three_finger.tap",
+            Gesture::ThreeFingerSwipeUp => "three_finger.swipe_up",
+            Gesture::ThreeFingerSwipeDown => "three_finger.swipe_down",
+            Gesture::ThreeFingerSwipeLeft => "three_finger.swipe_left",
+            Gesture::ThreeFingerSwipeRight => "three_finger.swipe_right",
+            Gesture::FourFingerSwipeUp => "four_finger.swipe_up",
+            Gesture::FourFingerSwipeDown => "four_finger.swipe_down",
+            Gesture::FourFingerSwipeLeft => "four_finger.swipe_left",
+            Gesture::FourFingerSwipeRight => "four_finger.swipe_right",
+            Gesture::OneFingerHold => "one_finger.hold",
+            Gesture::TwoFingerHold => "two_finger.hold",
+            Gesture::ThreeFingerHold => "three_finger.hold",
+        }
+    }
+
+    fn hold(fingers: usize) -> Option<Gesture> {
+        match fingers {
+            1 => Some(Gesture::OneFingerHold),
+            2 => Some(Gesture::TwoFingerHold),
+            3 => Some(Gesture::ThreeFingerHold),
+            _ => None,
+        }
+    }
+
+    /// The swipe gesture for `fingers` (2..=4) moving in `dir`.
+    fn swipe(fingers: usize, dir: Dir) -> Option<Gesture> {
+        use Gesture::*;
+        Some(match (fingers, dir) {
+            (2, Dir::Up) => TwoFingerSwipeUp,
+            (2, Dir::Down) => TwoFingerSwipeDown,
+            (2, Dir::Left) => TwoFingerSwipeLeft,
+            (2, Dir::Right) => TwoFingerSwipeRight,
+            (3, Dir::Up) => ThreeFingerSwipeUp,
+            (3, Dir::Down) => ThreeFingerSwipeDown,
+            (3, Dir::Left) => ThreeFingerSwipeLeft,
+            (3, Dir::Right) => ThreeFingerSwipeRight,
+            (4, Dir::Up) => FourFingerSwipeUp,
+            (4, Dir::Down) => FourFingerSwipeDown,
+            (4, Dir::Left) => FourFingerSwipeLeft,
+            (4, Dir::Right) => FourFingerSwipeRight,
+            _ => return None,
+        })
+    }
+}
+
+/// A continuous gesture, reported in phases while the fingers move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    /// Two to four fingers moving together.
+    Swipe(usize),
+    /// Two fingers spreading or closing.
+    Pinch,
+    /// Two or more fingers turning around their centroid.
+    Rotate,
+}
+
+impl Motion {
+    /// Binding key prefix; the phase is appended, e.g. `pinch.update`.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Motion::Swipe(2) => "two_finger.swipe",
+            Motion::Swipe(3) => "three_finger.swipe",
+            Motion::Swipe(_) => "four_finger.swipe",
+            Motion::Pinch => "pinch",
+            Motion::Rotate => "rotate",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Begin,
+    Update,
+    /// Fingers lifted.
+    End,
+    /// A finger was added, or the touch state was lost.
+    Cancel,
+}
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Begin => "begin",
+            Phase::Update => "update",
+            Phase::End => "end",
+            Phase::Cancel => "cancel",
+        }
+    }
+}
+
+/// Change in the fingers' pose: centroid translation in normalized units,
+/// span as a ratio (1.0 = unchanged) and rotation in degrees, clockwise
+/// positive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionDelta {
+    pub dx: f32,
+    pub dy: f32,
+    pub scale: f32,
+    pub rotation: f32,
+}
+
+impl Default for MotionDelta {
+    fn default() -> Self {
+        Self {
+            dx: 0.0,
+            dy: 0.0,
+            scale: 1.0,
+            rotation: 0.0,
+        }
+    }
+}
+
+/// One step of a continuous gesture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhaseEvent {
+    pub motion: Motion,
+    pub phase: Phase,
+    /// Since the previous event; for `Begin`, since the fingers landed, and
+    /// nothing for `End`/`Cancel`.
+    pub delta: MotionDelta,
+    /// Since the fingers landed.
+    pub total: MotionDelta,
+    /// Centroid velocity in normalized units per second.
+    pub velocity: (f32, f32),
+    /// Whole steps crossed by an `Update`: span change in
+    /// `pinch_span_step`s (spreading positive), turn in `rotate_step_deg`s
+    /// (clockwise positive) or travel in `swipe_min_dist`s. Zero for the
+    /// other phases.
+    pub steps: i32,
+}
+
+impl PhaseEvent {
+    /// Profile binding key, e.g. `three_finger.swipe.update`.
+    pub fn binding_key(&self) -> String {
+        format!("{}.{}", self.motion.key(), self.phase.name())
+    }
+}
+
+/// What a continuous gesture is measured from.
+#[derive(Debug, Default, Clone, Copy)]
+struct Pose {
+    t_ms: u128,
+    centroid: (f32, f32),
+    span: f32,
+    // rotation summed over the frames since the fingers landed
+    angle: f32,
+}
+
+impl Pose {
+    fn of(frame: &FrameSummary, angle: f32) -> Self {
+        Self {
+            t_ms: frame.timestamp_ms,
+            centroid: frame.centroid,
+            span: frame.span,
+            angle,
+        }
+    }
+
+    fn to(&self, other: &Pose) -> MotionDelta {
+        MotionDelta {
+            dx: other.centroid.0 - self.centroid.0,
+            dy: other.centroid.1 - self.centroid.1,
+            scale: if self.span > 0.0 {
+                other.span / self.span
+            } else {
+                1.0
+            },
+            rotation: other.angle - self.angle,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dir {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Direction of a centroid move from `from` to `to`, if it covered at least
+/// `swipe_min_dist` along its dominant axis.
+fn swipe_dir(from: (f32, f32), to: (f32, f32), th: &Thresholds) -> Option<Dir> {
+    let min_dist = th.swipe_min_dist;
+    let dx = to.0 - from.0;
+    let dy = (to.1 - from.1) * th.y_scale;
+    let (ax, ay) = (dx.abs(), dy.abs());
+    if ax >= ay && ax >= min_dist {
+        Some(if dx > 0.0 { Dir::Right } else { Dir::Left })
+    } else if ay > ax && ay >= min_dist {
+        Some(if dy > 0.0 { Dir::Down } else { Dir::Up })
+    } else {
+        None
+    }
 }
 
 #[derive(Debug, Default, Clone)]
 struct TwoFingerState {
     start_time_ms: u128,
     start_centroid: (f32, f32),
-    start_span: f32,
+    // span the next pinch step is measured from
+    pinch_span: f32,
     armed: bool,
     classified: bool,
+    // classified as a pinch; keeps stepping while the fingers stay down
+    pinching: bool,
+    // pinch steps the current frame crossed beyond the first
+    extra_steps: usize,
+}
+
+/// Three- or four-finger swipe tracking. Re-armed whenever the finger count
+/// changes, since the centroid jumps when a finger lands or lifts.
+#[derive(Debug, Default, Clone)]
+struct MultiFingerState {
+    fingers: usize,
+    start_time_ms: u128,
+    start_centroid: (f32, f32),
+    // one swipe per session; sticks until fewer than three fingers remain
+    classified: bool,
+}
+
+/// Continuous gesture tracking. Armed when a finger lands; begins once the
+/// fingers move or spread by more than `move_tol`, and lasts until the finger
+/// count changes. Like holds, lifting a finger ends it until the next lands.
+#[derive(Debug, Default, Clone)]
+struct MotionState {
+    fingers: usize,
+    start: Pose,
+    // pose at the last event; `start` until the gesture begins
+    last: Pose,
+    active: Option<Motion>,
+    velocity: (f32, f32),
+    angle: f32,
+    // change since the last whole step, in the active motion's unit
+    step_acc: f32,
+    done: bool,
+}
+
+/// Rotation in `rotate_step_deg` steps, for as long as the finger count
+/// holds. Gives way to a swipe or pinch that was recognized first.
+#[derive(Debug, Default, Clone)]
+struct RotateState {
+    fingers: usize,
+    // turn not yet reported as a step
+    acc: f32,
+    rotating: bool,
+}
+
+/// One- to three-finger hold. Armed whenever a finger is added; fires from
+/// [`GestureDetector::tick`] once `hold_ms` passes without any finger moving
+/// more than `move_tol`. Lifting a finger ends it until the next one lands.
+#[derive(Debug, Default, Clone)]
+struct HoldState {
+    fingers: usize,
+    start_ms: u128,
+    // (tracking id, moved_norm) of each finger when the hold was armed
+    baseline: Vec<(i32, f32)>,
+    // fired or cancelled by movement
+    done: bool,
 }
 
 #[derive(Debug)]
 pub struct GestureDetector {
     th: Thresholds,
     two: TwoFingerState,
+    multi: MultiFingerState,
+    hold: HoldState,
+    motion: MotionState,
+    rotate: RotateState,
+    // phase events not yet collected with `take_phases`
+    phases: Vec<PhaseEvent>,
     three_start_ms: Option<u128>,
     last_two_frame: Option<FrameSummary>, // ⬅️ NEW: stash the last frame with exactly two touches
 }
@@ -35,27 +319,220 @@ impl GestureDetector {
         Self {
             th,
             two: TwoFingerState::default(),
+            multi: MultiFingerState::default(),
+            hold: HoldState::default(),
+            motion: MotionState::default(),
+            rotate: RotateState::default(),
+            phases: Vec::new(),
             three_start_ms: None,
             last_two_frame: None,
         }
     }
 
+    /// Forget any gesture in progress, e.g. after touches were lost. A
+    /// continuous gesture in progress is cancelled.
+    pub fn reset(&mut self) {
+        if let Some(m) = self.motion.active {
+            self.emit(m, Phase::Cancel, MotionDelta::default(), 0);
+        }
+        self.motion = MotionState::default();
+        self.rotate = RotateState::default();
+        self.two = TwoFingerState::default();
+        self.multi = MultiFingerState::default();
+        self.hold = HoldState::default();
+        self.three_start_ms = None;
+        self.last_two_frame = None;
+    }
+
+    /// When a pending hold fires, in frame time; callers must [`tick`] at or
+    /// after it, and before feeding any later frame.
+    ///
+    /// [`tick`]: Self::tick
+    pub fn hold_deadline_ms(&self) -> Option<u128> {
+        (!self.hold.done && Gesture::hold(self.hold.fingers).is_some())
+            .then(|| self.hold.start_ms + self.th.hold_ms as u128)
+    }
+
+    /// Advance time to `now_ms` without a frame; returns the hold gesture if
+    /// its deadline has passed.
+    pub fn tick(&mut self, now_ms: u128) -> Option<Gesture> {
+        if now_ms < self.hold_deadline_ms()? {
+            return None;
+        }
+        self.hold.done = true;
+        // a hold is not a tap
+        match self.hold.fingers {
+            2 => self.two.classified = true,
+            3 => self.three_start_ms = None,
+            _ => {}
+        }
+        Gesture::hold(self.hold.fingers)
+    }
+
+    /// Phase events of continuous gestures since the last call, oldest first.
+    pub fn take_phases(&mut self) -> Vec<PhaseEvent> {
+        std::mem::take(&mut self.phases)
+    }
+
+    fn emit(&mut self, motion: Motion, phase: Phase, delta: MotionDelta, steps: i32) {
+        self.phases.push(PhaseEvent {
+            motion,
+            phase,
+            delta,
+            total: self.motion.start.to(&self.motion.last),
+            velocity: self.motion.velocity,
+            steps,
+        });
+    }
+
+    /// Change from `from` to `to` in `motion`'s step unit, and that unit.
+    fn step_amount(&self, motion: Motion, from: &Pose, to: &Pose) -> (f32, f32) {
+        match motion {
+            Motion::Pinch => (to.span - from.span, self.th.pinch_span_step()),
+            Motion::Rotate => (to.angle - from.angle, self.th.rotate_step_deg),
+            Motion::Swipe(_) => (
+                self.th.dist(
+                    to.centroid.0 - from.centroid.0,
+                    to.centroid.1 - from.centroid.1,
+                ),
+                self.th.swipe_min_dist,
+            ),
+        }
+    }
+
+    fn track_motion(&mut self, frame: &FrameSummary) {
+        let a = frame.active_count;
+        if a != self.motion.fingers {
+            let now = Pose::of(frame, 0.0);
+            if let Some(m) = self.motion.active {
+                let phase = if a > self.motion.fingers {
+                    Phase::Cancel
+                } else {
+                    Phase::End
+                };
+                self.emit(m, phase, MotionDelta::default(), 0);
+            }
+            self.motion = MotionState {
+                fingers: a,
+                start: now,
+                last: now,
+                active: None,
+                velocity: (0.0, 0.0),
+                angle: 0.0,
+                step_acc: 0.0,
+                done: a < self.motion.fingers || !(2..=4).contains(&a),
+            };
+            return;
+        }
+        if self.motion.done {
+            return;
+        }
+        self.motion.angle += frame.rotation;
+        let now = Pose::of(frame, self.motion.angle);
+
+        let delta = self.motion.last.to(&now);
+        let dt_ms = now.t_ms.saturating_sub(self.motion.last.t_ms);
+        if dt_ms > 0 {
+            let per_s = 1000.0 / dt_ms as f32;
+            self.motion.velocity = (delta.dx * per_s, delta.dy * per_s);
+        }
+        match self.motion.active {
+            Some(m) => {
+                let (amount, step) = self.step_amount(m, &self.motion.last, &now);
+                let mut steps = 0;
+                if step > 0.0 {
+                    self.motion.step_acc += amount;
+                    let n = (self.motion.step_acc / step).trunc();
+                    self.motion.step_acc -= n * step;
+                    steps = n as i32;
+                }
+                self.motion.last = now;
+                self.emit(m, Phase::Update, delta, steps);
+            }
+            None => {
+                let moved = self.th.dist(delta.dx, delta.dy);
+                let spread = (now.span - self.motion.start.span).abs();
+                // how far the fingers travelled turning
+                let arc = now.span * delta.rotation.to_radians().abs();
+                if moved.max(spread).max(arc) > self.th.move_tol {
+                    let m = if arc > moved.max(spread) {
+                        Motion::Rotate
+                    } else if a == 2 && spread > moved {
+                        Motion::Pinch
+                    } else {
+                        Motion::Swipe(a)
+                    };
+                    // counted towards the first update's steps
+                    self.motion.step_acc = self.step_amount(m, &self.motion.start, &now).0;
+                    self.motion.active = Some(m);
+                    self.motion.last = now;
+                    self.emit(m, Phase::Begin, delta, 0);
+                }
+            }
+        }
+    }
+
+    /// Use new thresholds from the next frame on; in-flight state is kept.
+    pub fn set_thresholds(&mut self, th: Thresholds) {
+        self.th = th;
+    }
+
+    /// Feed one frame; returns the gestures it completed. A pinch that
+    /// crossed several steps within the frame reports each of them.
     pub fn update(
         &mut self,
         frame: &FrameSummary,
         _prev: Option<&FrameSummary>, // no longer relied on for taps
-    ) -> Option<Gesture> {
+    ) -> Vec<Gesture> {
+        let Some(gesture) = self.classify(frame) else {
+            return Vec::new();
+        };
+        let repeats = 1 + std::mem::take(&mut self.two.extra_steps);
+        vec![gesture; repeats]
+    }
+
+    fn classify(&mut self, frame: &FrameSummary) -> Option<Gesture> {
         let a = frame.active_count;
 
+        // --- continuous gestures, reported alongside the one-shot ones ---
+        self.track_motion(frame);
+
+        // --- hold: arm when a finger lands, cancel on movement or lift ---
+        if a != self.hold.fingers {
+            let added = a > self.hold.fingers;
+            self.hold = HoldState {
+                fingers: a,
+                start_ms: frame.timestamp_ms,
+                baseline: frame
+                    .slots
+                    .iter()
+                    .map(|s| (s.tracking_id, s.moved_norm))
+                    .collect(),
+                done: !added,
+            };
+        } else if !self.hold.done {
+            let moved = frame.slots.iter().any(|s| {
+                let base = self
+                    .hold
+                    .baseline
+                    .iter()
+                    .find(|(id, _)| *id == s.tracking_id)
+                    .map_or(0.0, |(_, m)| *m);
+                s.moved_norm - base > self.th.move_tol
+            });
+            self.hold.done = moved;
+        }
+
         // --- track the last exact-2 frame for stable tap detection ---
         if a == 2 {
             // (re)arm on entering/staying in 2-finger state
             if !self.two.armed {
                 self.two.armed = true;
                 self.two.classified = false;
+                self.two.pinching = false;
                 self.two.start_time_ms = frame.timestamp_ms;
                 self.two.start_centroid = frame.centroid;
-                self.two.start_span = frame.span;
+                self.two.pinch_span = frame.span;
             }
             // always keep the freshest two-finger snapshot
             self.last_two_frame = Some(frame.clone());
@@ -63,32 +540,25 @@ impl GestureDetector {
             // while still in 2-finger state, allow swipe / pinch classification
             if !self.two.classified {
                 // swipe?
-                let dt = (frame.timestamp_ms - self.two.start_time_ms) as u64;
-                if dt <= self.th.swipe_max_ms {
-                    let dx = frame.centroid.0 - self.two.start_centroid.0;
-                    let dy = frame.centroid.1 - self.two.start_centroid.1;
-                    let ax = dx.abs();
-                    let ay = dy.abs();
-                    if ax >= ay && ax >= self.th.swipe_min_dist {
-                        self.two.classified = true;
-                        return Some(if dx > 0.0 {
-                            Gesture::TwoFingerSwipeRight
-                        } else {
-                            Gesture::TwoFingerSwipeLeft
-                        });
-                    } else if ay > ax && ay >= self.th.swipe_min_dist {
-                        self.two.classified = true;
-                        return Some(if dy > 0.0 {
-                            Gesture::TwoFingerSwipeDown
-                        } else {
-                            Gesture::TwoFingerSwipeUp
-                        });
-                    }
+                let dt = frame.timestamp_ms.saturating_sub(self.two.start_time_ms) as u64;
+                if dt <= self.th.swipe_max_ms
+                    && let Some(dir) = swipe_dir(self.two.start_centroid, frame.centroid, &self.th)
+                {
+                    self.two.classified = true;
+                    return Gesture::swipe(2, dir);
                 }
-                // pinch?
-                let dspan = frame.span - self.two.start_span;
-                if dspan.abs() >= self.th.pinch_step {
+            }
+            // pinch? one step per pinch_span_step of span change, for as
+            // long as the fingers stay down
+            if !self.two.classified || self.two.pinching {
+                let step = self.th.pinch_span_step();
+                let dspan = frame.span - self.two.pinch_span;
+                if dspan.abs() >= step {
+                    let steps = (dspan.abs() / step).floor();
                     self.two.classified = true;
+                    self.two.pinching = true;
+                    self.two.pinch_span += step.copysign(dspan) * steps;
+                    self.two.extra_steps = steps as usize - 1;
                     return Some(if dspan < 0.0 {
                         Gesture::PinchScaleIn
                     } else {
@@ -102,9 +572,12 @@ impl GestureDetector {
                 if !self.two.classified {
                     // evaluate TAP using the *saved* last two-finger frame
                     if let Some(last2) = &self.last_two_frame {
+                        // a still finger produces no frames, so age it up to the release
+                        let held_ms = frame.timestamp_ms.saturating_sub(last2.timestamp_ms) as u64;
                         let tap_ok = last2.slots.len() == 2
                             && last2.slots.iter().all(|s| {
-                                s.age_ms <= self.th.tap_ms && s.moved_norm <= self.th.move_tol
+                                s.age_ms + held_ms <= self.th.tap_ms
+                                    && s.moved_norm <= self.th.move_tol
                             });
                         if tap_ok {
                             self.two = TwoFingerState::default();
@@ -119,21 +592,831 @@ impl GestureDetector {
             }
         }
 
+        // --- three- and four-finger swipes ---
+        if a == 3 || a == 4 {
+            if self.multi.fingers != a {
+                self.multi.fingers = a;
+                self.multi.start_time_ms = frame.timestamp_ms;
+                self.multi.start_centroid = frame.centroid;
+            }
+            let dt = frame.timestamp_ms.saturating_sub(self.multi.start_time_ms) as u64;
+            if !self.multi.classified
+                && dt <= self.th.swipe_max_ms
+                && let Some(dir) = swipe_dir(self.multi.start_centroid, frame.centroid, &self.th)
+            {
+                self.multi.classified = true;
+                // a swipe is not a tap
+                self.three_start_ms = None;
+                return Gesture::swipe(a, dir);
+            }
+        } else if a < 3 {
+            self.multi = MultiFingerState::default();
+        }
+
+        // --- rotation, one step per rotate_step_deg ---
+        if a >= 2 {
+            if a != self.rotate.fingers {
+                self.rotate = RotateState {
+                    fingers: a,
+                    ..RotateState::default()
+                };
+            }
+            let classified = if a == 2 {
+                self.two.classified
+            } else {
+                self.multi.classified
+            };
+            if self.rotate.rotating || !classified {
+                self.rotate.acc += frame.rotation;
+                let step = self.th.rotate_step_deg;
+                if self.rotate.acc.abs() >= step {
+                    let cw = self.rotate.acc > 0.0;
+                    self.rotate.acc -= step.copysign(self.rotate.acc);
+                    self.rotate.rotating = true;
+                    // a rotation is not a tap, swipe or pinch
+                    if a == 2 {
+                        self.two.classified = true;
+                    } else {
+                        self.multi.classified = true;
+                    }
+                    self.three_start_ms = None;
+                    return Some(if cw {
+                        Gesture::RotateClockwise
+                    } else {
+                        Gesture::RotateCounterClockwise
+                    });
+                }
+            }
+        } else {
+            self.rotate = RotateState::default();
+        }
+
         // --- three-finger tap (unchanged logic, but benefits from the same robustness) ---
-        if a == 3 {
+        if a == 3 && !self.multi.classified {
             if self.three_start_ms.is_none() {
                 self.three_start_ms = Some(frame.timestamp_ms);
             }
-        } else if a == 0 {
-            if let Some(t0) = self.three_start_ms.take() {
-                let dt = (frame.timestamp_ms - t0) as u64;
-                // we can afford to be lenient here; noises are smaller with three down
-                if dt <= self.th.tap_ms {
-                    return Some(Gesture::ThreeFingerTap);
-                }
+        } else if a == 0
+            && let Some(t0) = self.three_start_ms.take()
+        {
+            let dt = frame.timestamp_ms.saturating_sub(t0) as u64;
+            // we can afford to be lenient here; noises are smaller with three down
+            if dt <= self.th.tap_ms {
+                return Some(Gesture::ThreeFingerTap);
             }
         }
 
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Gesture::{self, *};
+    use super::{GestureDetector, Motion, Phase, PhaseEvent};
+    use crate::config::Thresholds;
+    use crate::testkit::{Script, th};
+    use crate::tracker::{FrameSummary, SlotSnapshot, Zone};
+
+    /// Two fingers 0.2 apart, centred at (0.5, 0.5).
+    fn two_down() -> Script {
+        Script::new().down(0, 0.4, 0.5).down(1, 0.6, 0.5).sync()
+    }
+
+    fn two_swipe(dx: f32, dy: f32, ms: u64) -> Script {
+        two_down()
+            .move_to(0, 0.4 + dx, 0.5 + dy)
+            .move_to(1, 0.6 + dx, 0.5 + dy)
+            .over(ms)
+            .lift(0)
+            .lift(1)
+            .sync()
+    }
+
+    /// Symmetric pinch: each finger moves `d` towards (-) or away from (+) the centre.
+    fn pinch(d: f32, ms: u64) -> Script {
+        two_down()
+            .move_to(0, 0.4 - d, 0.5)
+            .move_to(1, 0.6 + d, 0.5)
+            .over(ms)
+            .lift(0)
+            .lift(1)
+            .sync()
+    }
+
+    /// `n` fingers side by side 0.1 apart, all moving by `(dx, dy)` over `ms`.
+    fn multi_swipe(n: usize, dx: f32, dy: f32, ms: u64) -> Script {
+        let x = |f: usize| 0.35 + 0.1 * f as f32;
+        let mut s = Script::new();
+        for f in 0..n {
+            s = s.down(f, x(f), 0.5);
+        }
+        s = s.sync();
+        for f in 0..n {
+            s = s.move_to(f, x(f) + dx, 0.5 + dy);
+        }
+        s = s.over(ms);
+        for f in 0..n {
+            s = s.lift(f);
+        }
+        s.sync()
+    }
+
+    /// `n` fingers evenly around (0.5, 0.5), 0.1 out, turning by `deg`
+    /// (clockwise positive) over `ms`.
+    fn turn(n: usize, deg: f32, ms: u64) -> Script {
+        let at = |f: usize, by: f32| {
+            let a = (f as f32 * 360.0 / n as f32 + by).to_radians();
+            (0.5 + 0.1 * a.cos(), 0.5 + 0.1 * a.sin())
+        };
+        let mut s = Script::new();
+        for f in 0..n {
+            let (x, y) = at(f, 0.0);
+            s = s.down(f, x, y);
+        }
+        s = s.sync();
+        for f in 0..n {
+            let (x, y) = at(f, deg);
+            s = s.move_to(f, x, y);
+        }
+        s = s.over(ms);
+        for f in 0..n {
+            s = s.lift(f);
+        }
+        s.sync()
+    }
+
+    fn three_tap(hold: u64) -> Script {
+        Script::new()
+            .down(0, 0.3, 0.5)
+            .down(1, 0.5, 0.5)
+            .down(2, 0.7, 0.5)
+            .sync()
+            .wait(hold)
+            .lift(0)
+            .lift(1)
+            .lift(2)
+            .sync()
+    }
+
+    struct Case {
+        name: &'static str,
+        script: Script,
+        th: Thresholds,
+        want: Vec<Gesture>,
+    }
+
+    fn case(name: &'static str, script: Script, want: &[Gesture]) -> Case {
+        Case {
+            name,
+            script,
+            th: th(),
+            want: want.to_vec(),
+        }
+    }
+
+    /// Edge zone along the bottom 10%, thumb zone over the bottom 25%.
+    fn zoned(name: &'static str, script: Script, want: &[Gesture]) -> Case {
+        let band = |y0| Zone {
+            x0: 0.0,
+            y0,
+            x1: 1.0,
+            y1: 1.0,
+        };
+        Case {
+            th: Thresholds {
+                edge_zones: vec![band(0.9)],
+                thumb_zone: Some(band(0.75)),
+                ..th()
+            },
+            ..case(name, script, want)
+        }
+    }
+
+    fn golden() -> Vec<Case> {
+        let t = th();
+        vec![
+            // --- two-finger tap ---
+            case(
+                "tap",
+                two_down().wait(80).lift(0).lift(1).sync(),
+                &[TwoFingerTap],
+            ),
+            case(
+                "tap at exactly tap_ms",
+                two_down().wait(t.tap_ms).lift(0).lift(1).sync(),
+                &[TwoFingerTap],
+            ),
+            case(
+                "tap held past tap_ms",
+                two_down().wait(t.tap_ms + 1).lift(0).lift(1).sync(),
+                &[],
+            ),
+            case(
+                "tap with jitter inside move_tol",
+                two_down()
+                    .move_to(0, 0.41, 0.5)
+                    .sync()
+                    .wait(50)
+                    .lift(0)
+                    .lift(1)
+                    .sync(),
+                &[TwoFingerTap],
+            ),
+            case(
+                "tap rejected by movement past move_tol",
+                two_down()
+                    .move_to(0, 0.43, 0.5)
+                    .sync()
+                    .wait(50)
+                    .lift(0)
+                    .lift(1)
+                    .sync(),
+                &[],
+            ),
+            // --- two-finger swipes ---
+            case("swipe up", two_swipe(0.0, -0.2, 120), &[TwoFingerSwipeUp]),
+            case(
+                "swipe down",
+                two_swipe(0.0, 0.2, 120),
+                &[TwoFingerSwipeDown],
+            ),
+            case(
+                "swipe left",
+                two_swipe(-0.2, 0.0, 120),
+                &[TwoFingerSwipeLeft],
+            ),
+            case(
+                "swipe right",
+                two_swipe(0.2, 0.0, 120),
+                &[TwoFingerSwipeRight],
+            ),
+            case(
+                "diagonal resolves to dominant axis",
+                two_swipe(0.15, -0.1, 120),
+                &[TwoFingerSwipeRight],
+            ),
+            case(
+                "swipe just short of swipe_min_dist",
+                two_swipe(0.0, -(t.swipe_min_dist - 0.01), 120),
+                &[],
+            ),
+            case(
+                "swipe slower than swipe_max_ms",
+                // moving from the start, so no hold, but swipe_min_dist is
+                // only reached after swipe_max_ms
+                two_down()
+                    .move_to(0, 0.4, 0.41)
+                    .move_to(1, 0.6, 0.41)
+                    .over(t.swipe_max_ms + 100)
+                    .lift(0)
+                    .lift(1)
+                    .sync(),
+                &[],
+            ),
+            case(
+                "resting past hold_ms before a swipe is a hold",
+                two_down()
+                    .wait(t.hold_ms + 10)
+                    .move_to(0, 0.4, 0.2)
+                    .move_to(1, 0.6, 0.2)
+                    .over(100)
+                    .lift(0)
+                    .lift(1)
+                    .sync(),
+                &[TwoFingerHold],
+            ),
+            case(
+                "only one swipe per two-finger session",
+                two_down()
+                    .move_to(0, 0.4, 0.3)
+                    .move_to(1, 0.6, 0.3)
+                    .over(100)
+                    .move_to(0, 0.4, 0.7)
+                    .move_to(1, 0.6, 0.7)
+                    .over(100)
+                    .lift(0)
+                    .lift(1)
+                    .sync(),
+                &[TwoFingerSwipeUp],
+            ),
+            // --- pinch ---
+            case("pinch in", pinch(-0.06, 100), &[PinchScaleIn]),
+            case("pinch out", pinch(0.06, 100), &[PinchScaleOut]),
+            case(
+                "pinch just short of pinch_step",
+                pinch(t.pinch_step - 0.01, 100),
+                &[],
+            ),
+            case(
+                "pinch steps repeat while the fingers stay down",
+                pinch(3.0 * t.pinch_step + 0.02, 200),
+                &[PinchScaleOut, PinchScaleOut, PinchScaleOut],
+            ),
+            case(
+                "one frame spreading past several pinch steps",
+                two_down()
+                    .move_to(0, 0.4 - 3.0 * t.pinch_step - 0.02, 0.5)
+                    .move_to(1, 0.6 + 3.0 * t.pinch_step + 0.02, 0.5)
+                    .sync()
+                    .lift(0)
+                    .lift(1)
+                    .sync(),
+                &[PinchScaleOut, PinchScaleOut, PinchScaleOut],
+            ),
+            case(
+                "pinch out then back in",
+                two_down()
+                    .move_to(0, 0.28, 0.5)
+                    .move_to(1, 0.72, 0.5)
+                    .over(100)
+                    .move_to(0, 0.42, 0.5)
+                    .move_to(1, 0.58, 0.5)
+                    .over(100)
+                    .lift(0)
+                    .lift(1)
+                    .sync(),
+                &[PinchScaleOut, PinchScaleOut, PinchScaleIn, PinchScaleIn],
+            ),
+            // --- three-finger tap ---
+            case("three-finger tap", three_tap(80), &[ThreeFingerTap]),
+            case(
+                "three-finger hold past tap_ms",
+                three_tap(t.tap_ms + 1),
+                &[],
+            ),
+            // --- rotation ---
+            case(
+                "rotate clockwise",
+                turn(2, 40.0, 100),
+                &[RotateClockwise, RotateClockwise],
+            ),
+            case(
+                "rotate counter-clockwise",
+                turn(2, -40.0, 100),
+                &[RotateCounterClockwise, RotateCounterClockwise],
+            ),
+            case("turn short of rotate_step_deg", turn(2, 10.0, 300), &[]),
+            case(
+                "three fingers rotate",
+                turn(3, 40.0, 100),
+                &[RotateClockwise, RotateClockwise],
+            ),
+            // --- three- and four-finger swipes ---
+            case(
+                "three-finger swipe up",
+                multi_swipe(3, 0.0, -0.2, 120),
+                &[ThreeFingerSwipeUp],
+            ),
+            case(
+                "three-finger swipe down",
+                multi_swipe(3, 0.0, 0.2, 120),
+                &[ThreeFingerSwipeDown],
+            ),
+            case(
+                "three-finger swipe left",
+                multi_swipe(3, -0.2, 0.0, 120),
+                &[ThreeFingerSwipeLeft],
+            ),
+            case(
+                "three-finger swipe right",
+                multi_swipe(3, 0.2, 0.0, 120),
+                &[ThreeFingerSwipeRight],
+            ),
+            case(
+                "four-finger swipe up",
+                multi_swipe(4, 0.0, -0.2, 120),
+                &[FourFingerSwipeUp],
+            ),
+            case(
+                "four-finger swipe down",
+                multi_swipe(4, 0.0, 0.2, 120),
+                &[FourFingerSwipeDown],
+            ),
+            case(
+                "four-finger swipe left",
+                multi_swipe(4, -0.2, 0.0, 120),
+                &[FourFingerSwipeLeft],
+            ),
+            case(
+                "four-finger swipe right",
+                multi_swipe(4, 0.2, 0.0, 120),
+                &[FourFingerSwipeRight],
+            ),
+            case(
+                "three-finger swipe short of swipe_min_dist",
+                multi_swipe(3, 0.0, -(t.swipe_min_dist - 0.01), 300),
+                &[],
+            ),
+            case(
+                "late fourth finger makes it a four-finger swipe",
+                Script::new()
+                    .down(0, 0.3, 0.5)
+                    .down(1, 0.4, 0.5)
+                    .down(2, 0.5, 0.5)
+                    .sync()
+                    .wait(30)
+                    .down(3, 0.6, 0.5)
+                    .sync()
+                    .move_to(0, 0.5, 0.5)
+                    .move_to(1, 0.6, 0.5)
+                    .move_to(2, 0.7, 0.5)
+                    .move_to(3, 0.8, 0.5)
+                    .over(100)
+                    .lift(0)
+                    .lift(1)
+                    .lift(2)
+                    .lift(3)
+                    .sync(),
+                &[FourFingerSwipeRight],
+            ),
+            // --- edge and thumb zones ---
+            zoned(
+                "resting thumb doesn't make a tap three fingers",
+                Script::new()
+                    .down(2, 0.5, 0.8)
+                    .sync()
+                    .wait(100)
+                    .down(0, 0.4, 0.5)
+                    .down(1, 0.6, 0.5)
+                    .sync()
+                    .wait(80)
+                    .lift(0)
+                    .lift(1)
+                    .sync(),
+                &[TwoFingerTap],
+            ),
+            zoned(
+                "touch that starts on the edge is ignored",
+                Script::new()
+                    .down(0, 0.5, 0.5)
+                    .down(1, 0.2, 0.95)
+                    .sync()
+                    .wait(80)
+                    .lift(0)
+                    .lift(1)
+                    .sync(),
+                &[],
+            ),
+            case(
+                "same touch counts without zones",
+                Script::new()
+                    .down(0, 0.5, 0.5)
+                    .down(1, 0.2, 0.95)
+                    .sync()
+                    .wait(80)
+                    .lift(0)
+                    .lift(1)
+                    .sync(),
+                &[TwoFingerTap],
+            ),
+            // --- holds ---
+            case(
+                "one-finger hold",
+                Script::new()
+                    .down(0, 0.5, 0.5)
+                    .sync()
+                    .wait(t.hold_ms)
+                    .lift(0)
+                    .sync(),
+                &[OneFingerHold],
+            ),
+            case(
+                "two-finger hold",
+                two_down().wait(t.hold_ms + 50).lift(0).lift(1).sync(),
+                &[TwoFingerHold],
+            ),
+            case(
+                "three-finger hold",
+                three_tap(t.hold_ms + 50),
+                &[ThreeFingerHold],
+            ),
+            case(
+                "hold with jitter inside move_tol",
+                two_down()
+                    .move_to(0, 0.41, 0.5)
+                    .sync()
+                    .wait(t.hold_ms)
+                    .lift(0)
+                    .lift(1)
+                    .sync(),
+                &[TwoFingerHold],
+            ),
+            case(
+                "hold cancelled by movement past move_tol",
+                two_down()
+                    .move_to(0, 0.45, 0.5)
+                    .move_to(1, 0.65, 0.5)
+                    .over(100)
+                    .wait(t.hold_ms)
+                    .lift(0)
+                    .lift(1)
+                    .sync(),
+                &[],
+            ),
+            case(
+                "adding a finger restarts the hold",
+                Script::new()
+                    .down(0, 0.4, 0.5)
+                    .sync()
+                    .wait(t.hold_ms - 100)
+                    .down(1, 0.6, 0.5)
+                    .sync()
+                    .wait(t.hold_ms - 100)
+                    .lift(0)
+                    .lift(1)
+                    .sync(),
+                &[],
+            ),
+            case(
+                "lifting a finger doesn't start a new hold",
+                two_down()
+                    .wait(t.hold_ms)
+                    .lift(1)
+                    .sync()
+                    .wait(t.hold_ms * 2)
+                    .lift(0)
+                    .sync(),
+                &[TwoFingerHold],
+            ),
+            // --- a quick single finger triggers nothing ---
+            case(
+                "one finger tap",
+                Script::new()
+                    .down(0, 0.5, 0.5)
+                    .sync()
+                    .wait(50)
+                    .lift(0)
+                    .sync(),
+                &[],
+            ),
+        ]
+    }
+
+    #[test]
+    fn golden_cases() {
+        let mut failures = vec![];
+        for c in golden() {
+            let got = c.script.run(c.th);
+            if got != c.want {
+                failures.push(format!("{}: want {:?}, got {:?}", c.name, c.want, got));
+            }
+        }
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn golden_covers_every_variant() {
+        let all = [
+            TwoFingerTap,
+            TwoFingerSwipeUp,
+            TwoFingerSwipeDown,
+            TwoFingerSwipeLeft,
+            TwoFingerSwipeRight,
+            PinchScaleIn,
+            PinchScaleOut,
+            RotateClockwise,
+            RotateCounterClockwise,
+            ThreeFingerTap,
+            ThreeFingerSwipeUp,
+            ThreeFingerSwipeDown,
+            ThreeFingerSwipeLeft,
+            ThreeFingerSwipeRight,
+            FourFingerSwipeUp,
+            FourFingerSwipeDown,
+            FourFingerSwipeLeft,
+            FourFingerSwipeRight,
+            OneFingerHold,
+            TwoFingerHold,
+            ThreeFingerHold,
+        ];
+        let seen: Vec<Gesture> = golden().into_iter().flat_map(|c| c.want).collect();
+        for g in all {
+            assert!(seen.contains(&g), "no golden case for {g:?}");
+        }
+    }
+
+    #[test]
+    fn hold_is_stamped_with_its_deadline() {
+        let hits = two_down().wait(1000).lift(0).lift(1).sync().run_timed(th());
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].gesture, TwoFingerHold);
+        assert_eq!(hits[0].t_ms, th().hold_ms as u128);
+    }
+
+    #[test]
+    fn mm_swipe_distance_is_the_same_along_both_axes() {
+        use crate::config::{DistanceUnit, NOMINAL_PAD_MM};
+        // the script's surface reports no size, so it is taken as nominal
+        assert_eq!(NOMINAL_PAD_MM, (100.0, 65.0));
+        let mm = Thresholds {
+            distance_unit: DistanceUnit::Mm,
+            move_tol: 2.5,
+            swipe_min_dist: 8.0,
+            pinch_step: 5.0,
+            kinetic_min_velocity: 30.0,
+            ..th()
+        };
+        // 7 mm and 9 mm each way
+        assert_eq!(two_swipe(0.07, 0.0, 120).run(mm.clone()), vec![]);
+        assert_eq!(
+            two_swipe(0.09, 0.0, 120).run(mm.clone()),
+            vec![TwoFingerSwipeRight]
+        );
+        assert_eq!(two_swipe(0.0, -7.0 / 65.0, 120).run(mm.clone()), vec![]);
+        assert_eq!(
+            two_swipe(0.0, -9.0 / 65.0, 120).run(mm),
+            vec![TwoFingerSwipeUp]
+        );
+    }
+
+    #[test]
+    fn default_profile_pinch_repeats() {
+        let th = crate::config::builtin_profile().unwrap().thresholds;
+        // fingers 2 cm apart on a 10 cm wide pad spreading to 5 cm
+        let spread = two_down()
+            .move_to(0, 0.25, 0.5)
+            .move_to(1, 0.75, 0.5)
+            .over(300)
+            .lift(0)
+            .lift(1)
+            .sync();
+        let hits = spread.run(th);
+        assert!(hits.len() >= 2, "{hits:?}");
+        assert!(hits.iter().all(|g| *g == PinchScaleOut), "{hits:?}");
+    }
+
+    #[test]
+    fn pinch_sensitivity_divides_the_step() {
+        let pinch_out = || pinch(0.06, 100);
+        assert_eq!(pinch_out().run(th()), vec![PinchScaleOut]);
+
+        let mut eager = th();
+        eager.pinch_sensitivity = 2.0;
+        assert_eq!(pinch_out().run(eager), vec![PinchScaleOut, PinchScaleOut]);
+    }
+
+    #[test]
+    fn swipe_max_ms_is_configurable() {
+        let slow = || two_swipe(0.0, -0.2, 300);
+        assert_eq!(slow().run(th()), vec![TwoFingerSwipeUp]);
+
+        let mut strict = th();
+        strict.swipe_max_ms = 20;
+        assert_eq!(slow().run(strict), vec![]);
+    }
+
+    #[test]
+    fn time_going_backwards_counts_as_no_time() {
+        // e.g. a device left on CLOCK_REALTIME whose clock was stepped back
+        let frame = |timestamp_ms, n: usize| FrameSummary {
+            timestamp_ms,
+            active_count: n,
+            centroid: (0.5, 0.5),
+            span: if n > 1 { 0.1 } else { 0.0 },
+            rotation: 0.0,
+            slots: (0..n)
+                .map(|i| SlotSnapshot {
+                    tracking_id: i as i32,
+                    x_norm: 0.4 + 0.1 * i as f32,
+                    y_norm: 0.5,
+                    moved_norm: 0.0,
+                    age_ms: 0,
+                    touch_major: 0.0,
+                    touch_minor: 0.0,
+                    pressure: 0.0,
+                    tool_type: 0,
+                })
+                .collect(),
+        };
+        for (n, tap) in [(2, TwoFingerTap), (3, ThreeFingerTap)] {
+            let mut d = GestureDetector::new(th());
+            assert_eq!(d.update(&frame(5000, n), None), []);
+            assert_eq!(d.update(&frame(4000, n), None), []);
+            assert_eq!(d.update(&frame(3990, 0), None), [tap]);
+        }
+    }
+
+    #[test]
+    fn gesture_is_stamped_with_frame_time() {
+        let hits = two_swipe(0.0, -0.2, 200).run_timed(th());
+        assert_eq!(hits.len(), 1);
+        // 0.08 of a 0.2 travel over 200 ms is crossed at the 80 ms frame
+        assert_eq!(hits[0].t_ms, 80);
+    }
+
+    fn phases_of(events: &[PhaseEvent]) -> Vec<(Motion, Phase)> {
+        let mut out: Vec<(Motion, Phase)> = events.iter().map(|e| (e.motion, e.phase)).collect();
+        out.dedup();
+        out
+    }
+
+    #[test]
+    fn still_fingers_report_no_phases() {
+        assert!(
+            two_down()
+                .wait(100)
+                .lift(0)
+                .lift(1)
+                .sync()
+                .run_phases(th())
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn swipe_phases_carry_translation_and_velocity() {
+        let ev = multi_swipe(3, 0.2, 0.0, 100).run_phases(th());
+        let s = Motion::Swipe(3);
+        assert_eq!(
+            phases_of(&ev),
+            [(s, Phase::Begin), (s, Phase::Update), (s, Phase::End)]
+        );
+        let end = ev.last().unwrap();
+        assert!((end.total.dx - 0.2).abs() < 1e-4, "{end:?}");
+        assert!(end.total.dy.abs() < 1e-4);
+        // 0.2 over 100 ms
+        assert!((end.velocity.0 - 2.0).abs() < 0.1, "{end:?}");
+
+        // the deltas add up to the total
+        let sum: f32 = ev.iter().map(|e| e.delta.dx).sum();
+        assert!((sum - end.total.dx).abs() < 1e-4);
+    }
+
+    #[test]
+    fn pinch_phases_carry_scale_and_rotation() {
+        let ev = pinch(0.1, 100).run_phases(th());
+        let p = Motion::Pinch;
+        assert_eq!(
+            phases_of(&ev),
+            [(p, Phase::Begin), (p, Phase::Update), (p, Phase::End)]
+        );
+        // span 0.1 -> 0.2
+        let end = ev.last().unwrap();
+        assert!((end.total.scale - 2.0).abs() < 1e-2, "{end:?}");
+        let product: f32 = ev.iter().map(|e| e.delta.scale).product();
+        assert!((product - end.total.scale).abs() < 1e-3);
+    }
+
+    #[test]
+    fn rotate_phases_carry_the_angle() {
+        for n in [2, 3] {
+            let ev = turn(n, 90.0, 100).run_phases(th());
+            let r = Motion::Rotate;
+            assert_eq!(
+                phases_of(&ev),
+                [(r, Phase::Begin), (r, Phase::Update), (r, Phase::End)],
+                "{n} fingers"
+            );
+            let end = ev.last().unwrap();
+            assert!((end.total.rotation - 90.0).abs() < 0.5, "{end:?}");
+            assert!(end.total.dx.abs() < 1e-3 && end.total.dy.abs() < 1e-3);
+        }
+        let end = *turn(2, -45.0, 100).run_phases(th()).last().unwrap();
+        assert!((end.total.rotation + 45.0).abs() < 0.5, "{end:?}");
+    }
+
+    #[test]
+    fn updates_count_whole_steps() {
+        let steps = |s: Script| -> i32 { s.run_phases(th()).iter().map(|e| e.steps).sum() };
+        // span 0.1 -> 0.22 in 0.05 steps, and 0.1 -> 0.04
+        assert_eq!(steps(pinch(0.12, 100)), 2);
+        assert_eq!(steps(pinch(-0.06, 100)), -1);
+        assert_eq!(steps(turn(2, 100.0, 100)), 6);
+        assert_eq!(steps(turn(3, -50.0, 100)), -3);
+        // 0.2 travelled in 0.08 steps
+        assert_eq!(steps(multi_swipe(3, 0.2, 0.0, 100)), 2);
+        assert_eq!(steps(multi_swipe(3, -0.2, 0.0, 100)), 2);
+    }
+
+    #[test]
+    fn adding_a_finger_cancels_the_motion() {
+        let ev = two_down()
+            .move_to(0, 0.4, 0.3)
+            .move_to(1, 0.6, 0.3)
+            .over(100)
+            .down(2, 0.8, 0.3)
+            .sync()
+            .move_to(0, 0.4, 0.5)
+            .move_to(1, 0.6, 0.5)
+            .move_to(2, 0.8, 0.5)
+            .over(100)
+            .lift(0)
+            .lift(1)
+            .lift(2)
+            .sync()
+            .run_phases(th());
+        let (two, three) = (Motion::Swipe(2), Motion::Swipe(3));
+        assert_eq!(
+            phases_of(&ev),
+            [
+                (two, Phase::Begin),
+                (two, Phase::Update),
+                (two, Phase::Cancel),
+                (three, Phase::Begin),
+                (three, Phase::Update),
+                (three, Phase::End),
+            ]
+        );
+    }
+}
@@ -1,40 +1,392 @@
 //! Input device discovery & event stream (evdev 0.13.2 compatible)
 
-use evdev::{AbsoluteAxisCode, Device, EventType};
+use std::{
+    fs::OpenOptions,
+    io,
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::Result;
+use evdev::{
+    AbsInfo, AbsoluteAxisCode, Device, EventType, InputEvent, SynchronizationCode,
+    raw_stream::RawDevice,
+};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher, event::ModifyKind};
+
+use crate::tracker::{DEFAULT_SLOTS, FrameSummary, MAX_SLOTS, SlotValues, Tracker};
 
 #[derive(Debug, Clone)]
 pub struct DeviceInfo {
     pub path: String,
     pub name: String,
+    pub vendor: u16,
+    pub product: u16,
+    /// `BUS_*` id from linux/input.h; see [`bus_name`].
+    pub bus: u16,
+}
+
+/// Bus names accepted in `[devices]` rules.
+const BUS_NAMES: &[(u16, &str)] = &[
+    (0x01, "pci"),
+    (0x03, "usb"),
+    (0x05, "bluetooth"),
+    (0x06, "virtual"),
+    (0x11, "i8042"),
+    (0x13, "rs232"),
+    (0x18, "i2c"),
+    (0x19, "host"),
+    (0x1C, "spi"),
+    (0x1D, "rmi"),
+];
+
+pub fn bus_name(bus: u16) -> &'static str {
+    BUS_NAMES
+        .iter()
+        .find(|(id, _)| *id == bus)
+        .map(|(_, n)| *n)
+        .unwrap_or("other")
 }
 
+pub fn is_known_bus(name: &str) -> bool {
+    BUS_NAMES.iter().any(|(_, n)| n.eq_ignore_ascii_case(name))
+}
+
+const INPUT_DIR: &str = "/dev/input";
+
 pub fn discover_multitouch() -> Vec<DeviceInfo> {
     let mut out = vec![];
-    if let Ok(rd) = std::fs::read_dir("/dev/input") {
+    if let Ok(rd) = std::fs::read_dir(INPUT_DIR) {
         for e in rd.flatten() {
-            let p = e.path();
-            if p.file_name()
-                .and_then(|s| s.to_str())
-                .map(|s| s.starts_with("event"))
-                .unwrap_or(false)
-            {
-                if let Ok(dev) = Device::open(&p) {
-                    let has_abs = dev.supported_events().contains(EventType::ABSOLUTE);
-                    let axes = dev.supported_absolute_axes();
-                    let has_mt = axes.map_or(false, |a| {
-                        a.contains(AbsoluteAxisCode::ABS_MT_SLOT)
-                            && a.contains(AbsoluteAxisCode::ABS_MT_POSITION_X)
-                            && a.contains(AbsoluteAxisCode::ABS_MT_POSITION_Y)
-                    });
-                    if has_abs && has_mt {
-                        out.push(DeviceInfo {
-                            path: p.display().to_string(),
-                            name: dev.name().unwrap_or("unknown").to_string(),
-                        });
-                    }
-                }
+            if let Ok(Some(info)) = probe_multitouch(&e.path()) {
+                out.push(info);
             }
         }
     }
+    out.sort_by(|a, b| a.path.cmp(&b.path));
     out
 }
+
+fn is_event_node(p: &Path) -> bool {
+    p.file_name()
+        .and_then(|s| s.to_str())
+        .is_some_and(|s| s.starts_with("event"))
+}
+
+/// Open `p` and check for MT positions. `Ok(None)` means the node
+/// isn't a multitouch device; `Err` means it couldn't be opened (yet).
+pub fn probe_multitouch(p: &Path) -> std::io::Result<Option<DeviceInfo>> {
+    if !is_event_node(p) {
+        return Ok(None);
+    }
+    let dev = Device::open(p)?;
+    let has_abs = dev.supported_events().contains(EventType::ABSOLUTE);
+    let axes = dev.supported_absolute_axes();
+    // slots are optional: protocol A devices report anonymous contacts
+    let has_mt = axes.is_some_and(|a| {
+        a.contains(AbsoluteAxisCode::ABS_MT_POSITION_X)
+            && a.contains(AbsoluteAxisCode::ABS_MT_POSITION_Y)
+    });
+    let id = dev.input_id();
+    Ok((has_abs && has_mt).then(|| DeviceInfo {
+        path: p.display().to_string(),
+        name: dev.name().unwrap_or("unknown").to_string(),
+        vendor: id.vendor(),
+        product: id.product(),
+        bus: id.bus_type().0,
+    }))
+}
+
+/// An `event*` node appeared (or changed permissions) or went away.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hotplug {
+    Added(PathBuf),
+    Removed(PathBuf),
+}
+
+/// Watch `/dev/input` for `event*` nodes coming and going; `notify` is called
+/// after each one is queued. Keep the watcher alive for as long as the
+/// receiver is used.
+pub fn watch_input_dir(
+    notify: impl Fn() + Send + 'static,
+) -> Result<(RecommendedWatcher, Receiver<Hotplug>)> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let Ok(ev) = res else { return };
+        for p in ev.paths {
+            if !is_event_node(&p) {
+                continue;
+            }
+            let hp = match ev.kind {
+                // udev fixes up permissions after the node appears; retry then
+                EventKind::Create(_) | EventKind::Modify(ModifyKind::Metadata(_)) => {
+                    Hotplug::Added(p)
+                }
+                EventKind::Remove(_) => Hotplug::Removed(p),
+                _ => continue,
+            };
+            let _ = tx.send(hp);
+            notify();
+        }
+    })?;
+    watcher.watch(Path::new(INPUT_DIR), RecursiveMode::NonRecursive)?;
+    Ok((watcher, rx))
+}
+
+/// One absolute axis as reported by the kernel's absinfo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbsAxis {
+    pub min: i32,
+    pub max: i32,
+    /// Units per millimetre; 0 when the driver doesn't report it.
+    pub resolution: i32,
+}
+
+impl AbsAxis {
+    pub fn size_mm(&self) -> Option<f32> {
+        (self.resolution > 0).then(|| (self.max - self.min) as f32 / self.resolution as f32)
+    }
+}
+
+/// Position ranges of a touch surface, used to normalize raw coordinates,
+/// and how many touches it tracks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub x: AbsAxis,
+    pub y: AbsAxis,
+    /// ABS_MT_SLOT maximum + 1; 0 for protocol A devices, which have none.
+    pub slots: usize,
+    /// ABS_MT_PRESSURE range, if the device reports pressure.
+    pub pressure: Option<AbsAxis>,
+}
+
+impl Default for Geometry {
+    /// The 0..4096 box assumed when nothing better is known.
+    fn default() -> Self {
+        let axis = AbsAxis {
+            min: 0,
+            max: 4096,
+            resolution: 0,
+        };
+        Self {
+            x: axis,
+            y: axis,
+            slots: DEFAULT_SLOTS,
+            pressure: None,
+        }
+    }
+}
+
+impl Geometry {
+    /// Pick the ABS_MT_POSITION_X/Y and pressure ranges and the slot count
+    /// out of a device's absinfo.
+    pub fn from_absinfo(
+        absinfo: impl IntoIterator<Item = (AbsoluteAxisCode, AbsInfo)>,
+    ) -> Option<Self> {
+        let (mut x, mut y, mut slots, mut pressure) = (None, None, 0, None);
+        for (code, info) in absinfo {
+            let axis = AbsAxis {
+                min: info.minimum(),
+                max: info.maximum(),
+                resolution: info.resolution(),
+            };
+            match code {
+                AbsoluteAxisCode::ABS_MT_POSITION_X => x = Some(axis),
+                AbsoluteAxisCode::ABS_MT_POSITION_Y => y = Some(axis),
+                AbsoluteAxisCode::ABS_MT_PRESSURE => pressure = Some(axis),
+                AbsoluteAxisCode::ABS_MT_SLOT => {
+                    slots = (info.maximum().max(0) as usize + 1).min(MAX_SLOTS)
+                }
+                _ => {}
+            }
+        }
+        Some(Self {
+            x: x?,
+            y: y?,
+            slots,
+            pressure,
+        })
+    }
+
+    /// Physical (width, height) if both axes report a resolution.
+    pub fn size_mm(&self) -> Option<(f32, f32)> {
+        Some((self.x.size_mm()?, self.y.size_mm()?))
+    }
+
+    pub fn apply_to(&self, tracker: &mut Tracker) {
+        tracker.set_slot_count(self.slots);
+        tracker.set_norm_ranges(self.x.min, self.x.max, self.y.min, self.y.max);
+        if let Some(p) = self.pressure {
+            tracker.set_pressure_range(p.min, p.max);
+        }
+    }
+}
+
+/// Open `path` non-blocking without the evdev crate's own SYN_DROPPED
+/// handling, which only restores the current slot; the pipeline resyncs all
+/// slots itself (see [`read_mt_slots`]).
+pub fn open_raw(path: &str) -> io::Result<RawDevice> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)?;
+    RawDevice::try_from(file)
+}
+
+/// `EVIOCSCLOCKID`, `_IOW('E', 0xa0, int)` in linux/input.h.
+const EVIOCSCLOCKID: libc::Ioctl = 0x4004_45a0;
+
+/// `EVIOCGMTSLOTS(len)`, `_IOC(_IOC_READ, 'E', 0x0a, len)` in linux/input.h.
+fn eviocgmtslots(len: usize) -> libc::Ioctl {
+    ((2 << 30) | (len << 16) | (0x45 << 8) | 0x0a) as libc::Ioctl
+}
+
+/// Have the kernel stamp `dev`'s events with CLOCK_MONOTONIC instead of
+/// CLOCK_REALTIME, so gesture timing doesn't jump when the wall clock is set.
+pub fn use_monotonic_clock(dev: &impl AsRawFd) -> io::Result<()> {
+    let clock: libc::c_int = libc::CLOCK_MONOTONIC;
+    if unsafe { libc::ioctl(dev.as_raw_fd(), EVIOCSCLOCKID, &clock) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Read the current slot and every slot's tracking id and position.
+pub fn read_mt_slots(dev: &RawDevice) -> io::Result<(i32, Vec<SlotValues>)> {
+    let abs = dev.get_abs_state()?;
+    let slot_info = abs[AbsoluteAxisCode::ABS_MT_SLOT.0 as usize];
+    let count = (slot_info.maximum + 1).clamp(1, MAX_SLOTS as i32) as usize;
+
+    // the kernel fills values[] for the code given in the first element
+    let read = |code: AbsoluteAxisCode| -> io::Result<Vec<i32>> {
+        let mut buf = vec![0i32; count + 1];
+        buf[0] = code.0 as i32;
+        let len = buf.len() * std::mem::size_of::<i32>();
+        if unsafe { libc::ioctl(dev.as_raw_fd(), eviocgmtslots(len), buf.as_mut_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.remove(0);
+        Ok(buf)
+    };
+    let ids = read(AbsoluteAxisCode::ABS_MT_TRACKING_ID)?;
+    let xs = read(AbsoluteAxisCode::ABS_MT_POSITION_X)?;
+    let ys = read(AbsoluteAxisCode::ABS_MT_POSITION_Y)?;
+    let slots = (0..count)
+        .map(|i| SlotValues {
+            tracking_id: ids[i],
+            x: xs[i],
+            y: ys[i],
+        })
+        .collect();
+    Ok((slot_info.value, slots))
+}
+
+/// The kernel's buffer overflowed; what follows up to the next SYN_REPORT is
+/// incomplete.
+pub fn is_syn_dropped(ev: &RawEvent) -> bool {
+    ev.kind == EventType::SYNCHRONIZATION.0 && ev.code == SynchronizationCode::SYN_DROPPED.0
+}
+
+pub fn is_syn_report(ev: &RawEvent) -> bool {
+    ev.kind == EventType::SYNCHRONIZATION.0 && ev.code == SynchronizationCode::SYN_REPORT.0
+}
+
+/// A single evdev event detached from its device: what the pipeline consumes
+/// and what `touchctl record` writes to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawEvent {
+    /// Kernel timestamp of the event (see [`use_monotonic_clock`]).
+    pub time_us: u64,
+    pub kind: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl From<&InputEvent> for RawEvent {
+    fn from(ev: &InputEvent) -> Self {
+        let time_us = ev
+            .timestamp()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0);
+        Self {
+            time_us,
+            kind: ev.event_type().0,
+            code: ev.code(),
+            value: ev.value(),
+        }
+    }
+}
+
+/// Route one raw event into the tracker. Returns the frame closed by SYN_REPORT.
+/// The event's timestamp drives the tracker's clock.
+pub fn feed_tracker(tracker: &mut Tracker, ev: &RawEvent) -> Option<FrameSummary> {
+    tracker.set_clock_ms((ev.time_us / 1000) as u128);
+    if ev.kind == EventType::ABSOLUTE.0 {
+        match ev.code {
+            c if c == AbsoluteAxisCode::ABS_MT_SLOT.0 => tracker.on_slot(ev.value),
+            c if c == AbsoluteAxisCode::ABS_MT_TRACKING_ID.0 => tracker.on_tracking_id(ev.value),
+            c if c == AbsoluteAxisCode::ABS_MT_POSITION_X.0 => tracker.on_pos_x(ev.value),
+            c if c == AbsoluteAxisCode::ABS_MT_POSITION_Y.0 => tracker.on_pos_y(ev.value),
+            c if c == AbsoluteAxisCode::ABS_MT_TOUCH_MAJOR.0 => tracker.on_touch_major(ev.value),
+            c if c == AbsoluteAxisCode::ABS_MT_TOUCH_MINOR.0 => tracker.on_touch_minor(ev.value),
+            c if c == AbsoluteAxisCode::ABS_MT_PRESSURE.0 => tracker.on_pressure(ev.value),
+            c if c == AbsoluteAxisCode::ABS_MT_TOOL_TYPE.0 => tracker.on_tool_type(ev.value),
+            _ => {}
+        }
+    } else if is_syn_report(ev) {
+        return Some(tracker.on_syn_report());
+    } else if ev.kind == EventType::SYNCHRONIZATION.0
+        && ev.code == SynchronizationCode::SYN_MT_REPORT.0
+    {
+        tracker.on_mt_report();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ev(time_us: u64, kind: EventType, code: u16, value: i32) -> RawEvent {
+        RawEvent {
+            time_us,
+            kind: kind.0,
+            code,
+            value,
+        }
+    }
+
+    #[test]
+    fn frames_are_timed_by_event_timestamps() {
+        let abs = |t, c: AbsoluteAxisCode, v| ev(t, EventType::ABSOLUTE, c.0, v);
+        let syn = |t| {
+            ev(
+                t,
+                EventType::SYNCHRONIZATION,
+                SynchronizationCode::SYN_REPORT.0,
+                0,
+            )
+        };
+        // delivered in one batch, but the kernel saw them 120 ms apart
+        let batch = [
+            abs(5_000_000, AbsoluteAxisCode::ABS_MT_TRACKING_ID, 1),
+            abs(5_000_000, AbsoluteAxisCode::ABS_MT_POSITION_X, 100),
+            abs(5_000_000, AbsoluteAxisCode::ABS_MT_POSITION_Y, 100),
+            syn(5_000_000),
+            abs(5_120_000, AbsoluteAxisCode::ABS_MT_POSITION_X, 110),
+            syn(5_120_000),
+        ];
+        let mut t = Tracker::new();
+        let frames: Vec<FrameSummary> = batch
+            .iter()
+            .filter_map(|e| feed_tracker(&mut t, e))
+            .collect();
+        assert_eq!(frames[0].timestamp_ms, 5000);
+        assert_eq!(frames[1].timestamp_ms, 5120);
+        assert_eq!(frames[1].slots[0].age_ms, 120);
+    }
+}
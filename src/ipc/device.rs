@@ -0,0 +1,406 @@
+//! Per-device pipeline state.
+//!
+//! Every opened touch device gets its own tracker, detector, scroll and grab
+//! state, so fingers on a touchscreen and a touchpad never share MT slots.
+
+use anyhow::{Result, anyhow};
+use evdev::raw_stream::RawDevice;
+use log::{error, info, warn};
+use std::time::{Duration, Instant};
+
+use crate::actions::{ScrollAxis, UinputSink, WHEEL_UNITS_PER_DETENT};
+use crate::config::{Profile, Thresholds};
+use crate::gestures::{Gesture, GestureDetector, PhaseEvent};
+use crate::input::{self, DeviceInfo, Geometry, RawEvent};
+use crate::kinetic::Kinetic;
+use crate::tracker::{FrameSummary, Tracker};
+
+/// How often a coasting scroll is advanced.
+const KINETIC_TICK: Duration = Duration::from_millis(8);
+
+pub struct DeviceCtx {
+    pub info: DeviceInfo,
+    pub dev: RawDevice,
+    /// Identifies this device's fd in the pipeline's poller.
+    pub token: u64,
+    /// Name of the profile this device uses (assigned or the active one).
+    pub profile_name: String,
+    pub profile: Profile,
+    geometry: Geometry,
+    tracker: Tracker,
+    detector: GestureDetector,
+    /// The profile's thresholds resolved for this device's size.
+    th: Thresholds,
+    prev_frame: Option<FrameSummary>,
+    // last frame's event timestamp and when we processed it, to map hold
+    // deadlines (event time) onto the poll timer (Instant)
+    frame_clock: (u128, Instant),
+    scroll: Scroll,
+    kinetic: Kinetic,
+    last_tick: Instant,
+    grabbed: bool,
+    want_grab: Option<bool>,
+    // true between the first event of a frame and its SYN_REPORT
+    mid_frame: bool,
+    // SYN_DROPPED seen; skipping to the next SYN_REPORT, then resyncing
+    resyncing: bool,
+    syn_dropped: u64,
+}
+
+impl DeviceCtx {
+    pub fn open(
+        info: &DeviceInfo,
+        token: u64,
+        profile_name: &str,
+        profile: &Profile,
+    ) -> Result<Self> {
+        let dev = input::open_raw(&info.path)
+            .map_err(|e| anyhow!("failed to open {}: {e}", info.path))?;
+        if let Err(e) = input::use_monotonic_clock(&dev) {
+            warn!(
+                "{}: cannot switch to CLOCK_MONOTONIC ({e}); using wall-clock timestamps",
+                info.path
+            );
+        }
+        let geometry = dev
+            .get_absinfo()
+            .ok()
+            .and_then(Geometry::from_absinfo)
+            .unwrap_or_else(|| {
+                warn!("{}: no ABS_MT_POSITION ranges; assuming 0..4096", info.path);
+                Geometry::default()
+            });
+
+        let th = profile.thresholds.for_surface(geometry.size_mm());
+        let tracker = new_tracker(&geometry, &th);
+        Ok(Self {
+            info: info.clone(),
+            dev,
+            token,
+            profile_name: profile_name.to_string(),
+            profile: profile.clone(),
+            geometry,
+            tracker,
+            detector: GestureDetector::new(th.clone()),
+            th,
+            prev_frame: None,
+            frame_clock: (0, Instant::now()),
+            scroll: Scroll::default(),
+            kinetic: Kinetic::new(),
+            last_tick: Instant::now(),
+            grabbed: false,
+            want_grab: None,
+            mid_frame: false,
+            resyncing: false,
+            syn_dropped: 0,
+        })
+    }
+
+    /// One-line description for the daemon log.
+    pub fn describe(&self) -> String {
+        let g = &self.geometry;
+        let size = match g.size_mm() {
+            Some((w, h)) => format!("{w:.0}x{h:.0} mm"),
+            None => "size unknown".to_string(),
+        };
+        let slots = match g.slots {
+            0 => "protocol A".to_string(),
+            n => format!("{n} slots"),
+        };
+        format!(
+            "{} ({}), x {}..{} y {}..{}, {size}, {slots}",
+            self.info.name, self.info.path, g.x.min, g.x.max, g.y.min, g.y.max
+        )
+    }
+
+    pub fn mid_frame(&self) -> bool {
+        self.mid_frame
+    }
+
+    /// How many times the kernel dropped events for this device.
+    pub fn syn_dropped(&self) -> u64 {
+        self.syn_dropped
+    }
+
+    /// Switch to `profile`, resolving its thresholds for this device, and
+    /// return true if the profile name changed. Only call between frames.
+    pub fn set_profile(&mut self, name: &str, profile: &Profile) -> bool {
+        self.th = profile.thresholds.for_surface(self.geometry.size_mm());
+        self.tracker.set_palm_filter(self.th.palm_filter());
+        self.tracker.set_zones(self.th.zone_filter());
+        self.tracker.set_y_scale(self.th.y_scale);
+        self.detector.set_thresholds(self.th.clone());
+        self.profile = profile.clone();
+        if self.profile_name == name {
+            return false;
+        }
+        self.profile_name = name.to_string();
+        true
+    }
+
+    /// When the pipeline should wake up for this device without input.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let kinetic = self
+            .kinetic
+            .is_active()
+            .then(|| self.last_tick + KINETIC_TICK);
+        let hold = self.detector.hold_deadline_ms().map(|due| {
+            let (ms, at) = self.frame_clock;
+            at + Duration::from_millis(due.saturating_sub(ms) as u64)
+        });
+        kinetic.into_iter().chain(hold).min()
+    }
+
+    /// Fire a pending hold whose deadline has passed: before `ev` (by its
+    /// timestamp) if given, otherwise as of now.
+    pub fn tick_hold(&mut self, ev: Option<&RawEvent>) -> Option<Gesture> {
+        let now_ms = match ev {
+            Some(ev) => (ev.time_us / 1000) as u128,
+            None => {
+                let (ms, at) = self.frame_clock;
+                ms + at.elapsed().as_millis()
+            }
+        };
+        self.detector.tick(now_ms)
+    }
+
+    /// Keep inertial scrolling going between frames.
+    pub fn tick_kinetic(&mut self, sink: &mut UinputSink) {
+        if !self.kinetic.is_active() {
+            return;
+        }
+        let now = Instant::now();
+        match self.kinetic.tick(now - self.last_tick, &self.th) {
+            Some((dx, dy)) => self.scroll.by(sink, dx, dy, &self.th),
+            None => self.scroll.reset(),
+        }
+        self.last_tick = now;
+    }
+
+    /// Feed one event. On SYN_REPORT this drives scrolling and returns the
+    /// gestures the frame completed.
+    pub fn feed(&mut self, ev: &RawEvent, sink: &mut UinputSink) -> Vec<Gesture> {
+        self.mid_frame = true;
+        if input::is_syn_dropped(ev) {
+            warn!("{}: kernel dropped events; resyncing", self.info.path);
+            self.syn_dropped += 1;
+            self.resyncing = true;
+            return Vec::new();
+        }
+        if self.resyncing {
+            // everything up to the next SYN_REPORT is a partial frame
+            if input::is_syn_report(ev) {
+                self.tracker.set_clock_ms((ev.time_us / 1000) as u128);
+                self.resync(sink);
+            }
+            return Vec::new();
+        }
+        let Some(frame) = input::feed_tracker(&mut self.tracker, ev) else {
+            return Vec::new();
+        };
+        self.mid_frame = false;
+
+        // schedule grab/ungrab for the end of the read
+        self.want_grab = Some(sink.is_enabled() && frame.active_count >= 2);
+
+        // continuous scroll
+        if let Some(prev) = &self.prev_frame {
+            if frame.active_count == 2 {
+                let dspan = (frame.span - prev.span).abs();
+                let pinch_gate = 0.6 * self.th.pinch_span_step();
+
+                if dspan < pinch_gate {
+                    self.kinetic.sample(frame.timestamp_ms, frame.centroid);
+                    self.scroll.by(
+                        sink,
+                        frame.centroid.0 - prev.centroid.0,
+                        frame.centroid.1 - prev.centroid.1,
+                        &self.th,
+                    );
+                } else {
+                    // pinching, not scrolling: don't fling on lift
+                    self.kinetic.cancel();
+                }
+            } else if frame.active_count != prev.active_count {
+                let lock = self.scroll.lock;
+                self.scroll.reset();
+                if frame.active_count > prev.active_count {
+                    // a new touch stops any coasting scroll
+                    self.kinetic.cancel();
+                } else if prev.active_count == 2
+                    && self.kinetic.release(frame.timestamp_ms, &self.th)
+                {
+                    self.last_tick = Instant::now();
+                    // the fling carries on along the scroll's axis
+                    self.scroll.lock = lock;
+                }
+            }
+        }
+
+        let gestures = self.detector.update(&frame, self.prev_frame.as_ref());
+        self.frame_clock = (frame.timestamp_ms, Instant::now());
+        self.prev_frame = Some(frame);
+        gestures
+    }
+
+    /// Phase events of continuous gestures produced by [`feed`](Self::feed)
+    /// since the last call.
+    pub fn take_phases(&mut self) -> Vec<PhaseEvent> {
+        self.detector.take_phases()
+    }
+
+    /// Rebuild the touch state from the device after SYN_DROPPED and abandon
+    /// whatever gesture was in progress.
+    fn resync(&mut self, sink: &mut UinputSink) {
+        // protocol A has no slot state to read back, but its next frame
+        // lists every contact again
+        let state = match self.geometry.slots {
+            0 => None,
+            _ => input::read_mt_slots(&self.dev)
+                .map_err(|e| warn!("{}: cannot read MT slots ({e})", self.info.path))
+                .ok(),
+        };
+        match state {
+            Some((cur_slot, slots)) => self.tracker.resync(cur_slot, &slots),
+            None => {
+                // without the real state, assume every finger is up
+                self.tracker = new_tracker(&self.geometry, &self.th);
+            }
+        }
+        let frame = self.tracker.on_syn_report();
+        self.detector.reset();
+        self.kinetic.cancel();
+        self.scroll.reset();
+        self.want_grab = Some(sink.is_enabled() && frame.active_count >= 2);
+        self.prev_frame = Some(frame);
+        self.resyncing = false;
+        self.mid_frame = false;
+    }
+
+    /// Ask for the grab to be released at the end of this read.
+    pub fn release_grab(&mut self) {
+        if self.grabbed {
+            self.want_grab = Some(false);
+        }
+    }
+
+    /// Grab (>=2 fingers) or release the device as the last frame asked.
+    pub fn apply_grab(&mut self) {
+        match self.want_grab.take() {
+            Some(true) if !self.grabbed => {
+                let _ = self.dev.grab();
+                self.grabbed = true;
+                info!("grabbed {} (>=2 fingers)", self.info.path);
+            }
+            Some(false) if self.grabbed => {
+                let _ = self.dev.ungrab();
+                self.grabbed = false;
+                info!("released {} (<2 fingers)", self.info.path);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A tracker set up for a device's surface and resolved thresholds.
+fn new_tracker(geometry: &Geometry, th: &Thresholds) -> Tracker {
+    let mut tracker = Tracker::new();
+    geometry.apply_to(&mut tracker);
+    tracker.set_palm_filter(th.palm_filter());
+    tracker.set_zones(th.zone_filter());
+    tracker.set_y_scale(th.y_scale);
+    tracker
+}
+
+/// Two-finger scroll. Nothing is emitted until the centroid has travelled
+/// `move_tol`; the scroll then locks to the dominant axis until the finger
+/// count changes, so a vertical scroll doesn't drift sideways.
+#[derive(Debug, Default)]
+struct Scroll {
+    // (horizontal, vertical) centroid travel not yet emitted as wheel steps
+    acc: (f32, f32),
+    lock: Option<ScrollAxis>,
+}
+
+impl Scroll {
+    /// Drop partial steps and the axis lock.
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Add centroid travel, choosing the axis once there is enough of it.
+    fn add(&mut self, dx: f32, dy: f32, th: &Thresholds) {
+        self.acc.0 += dx;
+        self.acc.1 += dy;
+        if self.lock.is_none() && th.dist(self.acc.0, self.acc.1) > th.move_tol {
+            self.lock = Some(if self.acc.0.abs() >= (self.acc.1 * th.y_scale).abs() {
+                ScrollAxis::Horizontal
+            } else {
+                ScrollAxis::Vertical
+            });
+        }
+        match self.lock {
+            Some(ScrollAxis::Horizontal) => self.acc.1 = 0.0,
+            Some(ScrollAxis::Vertical) => self.acc.0 = 0.0,
+            None => {}
+        }
+    }
+
+    /// Add centroid travel and emit the whole hi-res units the locked axis
+    /// holds.
+    fn by(&mut self, sink: &mut UinputSink, dx: f32, dy: f32, th: &Thresholds) {
+        self.add(dx, dy, th);
+        let Some(axis) = self.lock else {
+            return;
+        };
+        let units = match axis {
+            ScrollAxis::Horizontal => take_scroll_units(&mut self.acc.0),
+            ScrollAxis::Vertical => -take_scroll_units(&mut self.acc.1),
+        };
+        if let Err(e) = sink.scroll_smooth(axis, units) {
+            error!("scroll emit failed: {e}");
+        }
+    }
+}
+
+/// Convert accumulated centroid travel into hi-res wheel units (120 per
+/// detent), keeping the sub-unit remainder.
+fn take_scroll_units(acc: &mut f32) -> i32 {
+    // centroid travel per detent
+    const STEP_NORM: f32 = 0.010;
+    const GAIN: f32 = 1.0;
+    const UNIT_NORM: f32 = STEP_NORM / WHEEL_UNITS_PER_DETENT as f32;
+
+    let units = ((*acc / UNIT_NORM) * GAIN) as i32;
+    if units != 0 {
+        *acc -= (units as f32) * UNIT_NORM / GAIN;
+    }
+    units
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::th;
+
+    #[test]
+    fn scroll_locks_to_the_dominant_axis() {
+        let th = th();
+        let mut scroll = Scroll::default();
+        scroll.add(0.005, 0.01, &th);
+        assert_eq!(scroll.lock, None, "short of move_tol");
+        scroll.add(0.005, 0.02, &th);
+        assert_eq!(scroll.lock, Some(ScrollAxis::Vertical));
+        assert_eq!(scroll.acc.0, 0.0);
+
+        // sideways drift is ignored while the lock holds
+        scroll.add(0.05, 0.001, &th);
+        assert_eq!(scroll.lock, Some(ScrollAxis::Vertical));
+        assert_eq!(scroll.acc.0, 0.0);
+
+        // e.g. a finger lifted and landed again
+        scroll.reset();
+        scroll.add(0.05, 0.001, &th);
+        assert_eq!(scroll.lock, Some(ScrollAxis::Horizontal));
+    }
+}
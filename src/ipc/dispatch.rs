@@ -1,34 +1,107 @@
 use crate::actions::UinputSink;
+use crate::command::{CommandRunner, CommandSpec};
 use crate::config::Profile;
-use crate::gestures::Gesture;
+use crate::gestures::{Gesture, Phase, PhaseEvent};
+use crate::input::DeviceInfo;
 use anyhow::{Result, anyhow};
-use std::sync::{Arc, Mutex};
+use log::info;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+/// Run the binding for a gesture from `device`. `cmd:` actions see the
+/// device in their environment.
 pub fn dispatch_gesture(
     g: &Gesture,
-    profile_arc: &Arc<Mutex<Profile>>,
+    device: &DeviceInfo,
+    profile: &Profile,
+    enabled: &AtomicBool,
     sink: &mut UinputSink,
+    runner: &CommandRunner,
 ) -> Result<()> {
-    let (key, action) = {
-        let p = profile_arc.lock().unwrap();
-        let key = match g {
-            Gesture::TwoFingerTap => "two_finger.tap",
-            Gesture::TwoFingerSwipeUp => "two_finger.swipe_up",
-            Gesture::TwoFingerSwipeDown => "two_finger.swipe_down",
-            Gesture::TwoFingerSwipeLeft => "two_finger.swipe_left",
-            Gesture::TwoFingerSwipeRight => "two_finger.swipe_right",
-            Gesture::PinchScaleIn => "pinch.scale_in",
-            Gesture::PinchScaleOut => "pinch.scale_out",
-            Gesture::ThreeFingerTap => "three_finger.tap",
-        };
-        let action = p.bindings.get(key).cloned().unwrap_or_default();
-        (key.to_string(), action)
+    let env = device_env(device);
+    run_binding(g.binding_key(), env, 1, profile, enabled, sink, runner)
+}
+
+/// Run the binding for one phase of a continuous gesture, e.g. `pinch.update`.
+/// `.update` bindings run once per step the update crossed, so a pinch bound
+/// to a key zooms in proportion to the span change; `scroll:` actions flip
+/// direction with the steps' sign. `cmd:` actions instead run on every
+/// update, so they can follow the motion, and see the phase, the steps and
+/// the device in their environment.
+pub fn dispatch_phase(
+    ev: &PhaseEvent,
+    device: &DeviceInfo,
+    profile: &Profile,
+    enabled: &AtomicBool,
+    sink: &mut UinputSink,
+    runner: &CommandRunner,
+) -> Result<()> {
+    let key = ev.binding_key();
+    let Some(action) = profile.bindings.get(&key) else {
+        return Ok(());
+    };
+    let repeat = match ev.phase {
+        Phase::Update if !action.starts_with("cmd:") => ev.steps.unsigned_abs(),
+        _ => 1,
     };
+    let sign = if ev.steps < 0 { -1 } else { 1 };
+    let mut env = device_env(device);
+    env.extend(phase_env(ev));
+    for _ in 0..repeat {
+        run_binding(&key, env.clone(), sign, profile, enabled, sink, runner)?;
+    }
+    Ok(())
+}
+
+/// `TOUCHCTL_*` variables naming the device a gesture came from.
+fn device_env(device: &DeviceInfo) -> Vec<(String, String)> {
+    vec![
+        ("TOUCHCTL_DEVICE".to_string(), device.path.clone()),
+        ("TOUCHCTL_DEVICE_NAME".to_string(), device.name.clone()),
+    ]
+}
+
+/// `TOUCHCTL_*` variables describing a phase event.
+fn phase_env(ev: &PhaseEvent) -> Vec<(String, String)> {
+    let vars = [
+        ("GESTURE", ev.motion.key().to_string()),
+        ("PHASE", ev.phase.name().to_string()),
+        ("DX", ev.delta.dx.to_string()),
+        ("DY", ev.delta.dy.to_string()),
+        ("SCALE", ev.delta.scale.to_string()),
+        ("ROTATION", ev.delta.rotation.to_string()),
+        ("TOTAL_DX", ev.total.dx.to_string()),
+        ("TOTAL_DY", ev.total.dy.to_string()),
+        ("TOTAL_SCALE", ev.total.scale.to_string()),
+        ("TOTAL_ROTATION", ev.total.rotation.to_string()),
+        ("VX", ev.velocity.0.to_string()),
+        ("VY", ev.velocity.1.to_string()),
+        ("STEPS", ev.steps.to_string()),
+    ];
+    vars.into_iter()
+        .map(|(k, v)| (format!("TOUCHCTL_{k}"), v))
+        .collect()
+}
+
+/// `sign` is -1 to run `scroll:` actions the other way.
+fn run_binding(
+    key: &str,
+    env: Vec<(String, String)>,
+    sign: i32,
+    profile: &Profile,
+    enabled: &AtomicBool,
+    sink: &mut UinputSink,
+    runner: &CommandRunner,
+) -> Result<()> {
+    let action = profile.bindings.get(key).cloned().unwrap_or_default();
 
     if action.is_empty() {
         return Ok(());
     }
     if action == "toggle" {
+        // shared flag is the source of truth so IPC `status` sees the change
+        let now = !enabled.fetch_xor(true, Ordering::Relaxed);
+        sink.set_enabled(now);
+        info!("toggle: {}", if now { "enabled" } else { "disabled" });
         return Ok(());
     }
 
@@ -38,11 +111,15 @@ pub fn dispatch_gesture(
     }
     if let Some(rest) = action.strip_prefix("scroll:") {
         let parts: Vec<_> = rest.split('@').collect();
-        let axis = parts.get(0).map(|s| s.trim()).unwrap_or("vertical");
+        let axis = parts.first().map(|s| s.trim()).unwrap_or("vertical");
         let steps_str = parts.get(1).copied().unwrap_or("+1");
-        let steps: i32 = steps_str.parse().unwrap_or(1);
+        let steps = sign * steps_str.parse::<i32>().unwrap_or(1);
         if axis.eq_ignore_ascii_case("vertical") {
             sink.scroll_vertical(steps)?;
+        } else if axis.eq_ignore_ascii_case("horizontal") {
+            sink.scroll_horizontal(steps)?;
+        } else {
+            return Err(anyhow!("unknown scroll axis for {}: '{}'", key, axis));
         }
         return Ok(());
     }
@@ -50,8 +127,11 @@ pub fn dispatch_gesture(
         sink.key_chord(rest.trim())?;
         return Ok(());
     }
-    if action.starts_with("cmd:") {
-        // gated elsewhere; implement later
+    if let Some(rest) = action.strip_prefix("cmd:") {
+        // allow_commands is enforced by validate_profile
+        if sink.is_enabled() {
+            runner.spawn(key, CommandSpec::parse(rest)?, env, &profile.commands)?;
+        }
         return Ok(());
     }
 
@@ -61,3 +141,98 @@ pub fn dispatch_gesture(
         action
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::builtin_profile;
+    use crate::gestures::{Motion, MotionDelta};
+
+    fn pad() -> DeviceInfo {
+        DeviceInfo {
+            path: "/dev/input/event5".into(),
+            name: "SynPS/2 Synaptics TouchPad".into(),
+            vendor: 0x2,
+            product: 0x7,
+            bus: 0x11,
+        }
+    }
+
+    fn update(steps: i32) -> PhaseEvent {
+        PhaseEvent {
+            motion: Motion::Pinch,
+            phase: Phase::Update,
+            delta: MotionDelta::default(),
+            total: MotionDelta::default(),
+            velocity: (0.0, 0.0),
+            steps,
+        }
+    }
+
+    #[test]
+    fn updates_run_once_per_step() {
+        let mut profile = builtin_profile().unwrap();
+        // toggling makes each run visible in the shared flag
+        profile
+            .bindings
+            .insert("pinch.update".into(), "toggle".into());
+        let enabled = AtomicBool::new(true);
+        let mut sink = UinputSink::noop();
+        let runner = CommandRunner::new();
+        let mut runs = |ev: PhaseEvent| {
+            let before = enabled.load(Ordering::Relaxed);
+            dispatch_phase(&ev, &pad(), &profile, &enabled, &mut sink, &runner).unwrap();
+            enabled.load(Ordering::Relaxed) != before
+        };
+
+        assert!(!runs(update(0)), "no step crossed");
+        assert!(runs(update(1)));
+        assert!(!runs(update(2)), "ran an even number of times");
+        assert!(runs(update(-3)));
+    }
+
+    #[test]
+    fn commands_run_once_per_update() {
+        let mut profile = builtin_profile().unwrap();
+        profile
+            .bindings
+            .insert("pinch.update".into(), "cmd:sleep 1".into());
+        // a second run while the first sleeps is refused
+        profile.commands.max_running = 1;
+        let enabled = AtomicBool::new(true);
+        let mut sink = UinputSink::noop();
+        let mut runs = |steps| {
+            let runner = CommandRunner::new();
+            dispatch_phase(
+                &update(steps),
+                &pad(),
+                &profile,
+                &enabled,
+                &mut sink,
+                &runner,
+            )
+            .map(|_| runner)
+        };
+
+        let runner = runs(0).expect("ran with no step crossed");
+        let again = CommandSpec::parse("true").unwrap();
+        assert!(
+            runner
+                .spawn("again", again, vec![], &profile.commands)
+                .is_err(),
+            "the update started its command"
+        );
+        assert!(runs(3).is_ok(), "ran once, not once per step");
+    }
+
+    #[test]
+    fn commands_see_the_source_device() {
+        let env = device_env(&pad());
+        let get = |k: &str| env.iter().find(|(n, _)| n == k).map(|(_, v)| v.as_str());
+        assert_eq!(get("TOUCHCTL_DEVICE"), Some("/dev/input/event5"));
+        assert_eq!(
+            get("TOUCHCTL_DEVICE_NAME"),
+            Some("SynPS/2 Synaptics TouchPad")
+        );
+    }
+}
@@ -1,6 +1,9 @@
+mod device;
 mod dispatch;
 mod pipeline;
+mod poll;
 mod runtime;
 mod server;
+mod watch;
 
 pub use server::{client_request, run_daemon};
@@ -1,149 +1,356 @@
 use anyhow::Result;
-use log::{error, info, warn};
-use std::{thread, time::Duration};
-
-use evdev::{AbsoluteAxisCode, Device, EventType, SynchronizationCode};
+use log::{debug, error, info, warn};
+use std::{collections::BTreeMap, io::ErrorKind, os::fd::AsRawFd, time::Duration};
 
+use super::device::DeviceCtx;
+use super::poll::{Poller, Timer, Waker, WakingSender};
 use super::server::DaemonEvent;
 use crate::actions::UinputSink;
-use crate::config::Profile;
-use crate::gestures::{Gesture, GestureDetector};
-use crate::input;
-use crate::tracker::{FrameSummary, Tracker};
-use std::sync::{Arc, Mutex};
+use crate::command::CommandRunner;
+use crate::config::ProfileSet;
+use crate::gestures::Gesture;
+use crate::input::{self, DeviceInfo, Hotplug, RawEvent};
+use std::sync::{
+    Arc, Condvar, Mutex,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+};
 
-pub fn run_pipeline(
-    profile: Arc<Mutex<Profile>>,
-    _tx_evt: std::sync::mpsc::Sender<DaemonEvent>,
-) -> Result<()> {
-    let devices = input::discover_multitouch();
-    if devices.is_empty() {
-        warn!("no multitouch devices detected; pipeline idle");
-        loop {
-            thread::sleep(Duration::from_secs(1));
+/// Profiles shared between the daemon and the pipeline. The daemon publishes
+/// new generations; the pipeline adopts them only between frames, so a frame
+/// is never evaluated against a mix of old and new settings.
+pub struct ProfileHandle {
+    profile: Mutex<ProfileSet>,
+    generation: AtomicU64,
+    /// Last generation the pipeline adopted; signalled on every adoption.
+    applied: Mutex<u64>,
+    adopted: Condvar,
+    /// Wakes the pipeline so it adopts a publish without waiting for input.
+    waker: Waker,
+}
+
+impl ProfileHandle {
+    pub fn new(profile: ProfileSet, waker: Waker) -> Self {
+        Self {
+            profile: Mutex::new(profile),
+            generation: AtomicU64::new(0),
+            applied: Mutex::new(0),
+            adopted: Condvar::new(),
+            waker,
         }
     }
 
-    let mut devs: Vec<Device> = vec![];
-    for d in devices {
-        match Device::open(&d.path) {
-            Ok(mut dev) => {
-                let _ = dev.set_nonblocking(true);
-                devs.push(dev);
-            }
-            Err(e) => warn!("failed to open {}: {e}", d.path),
-        }
+    /// Replace the profiles; returns the generation to wait on.
+    pub fn publish(&self, profile: ProfileSet) -> u64 {
+        let mut p = self.profile.lock().unwrap();
+        *p = profile;
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.waker.wake();
+        generation
+    }
+
+    /// Block until the pipeline has adopted `generation`, up to `timeout`.
+    pub fn wait_applied(&self, generation: u64, timeout: Duration) -> bool {
+        let applied = self.applied.lock().unwrap();
+        let (applied, _) = self
+            .adopted
+            .wait_timeout_while(applied, timeout, |a| *a < generation)
+            .unwrap();
+        *applied >= generation
     }
-    if devs.is_empty() {
-        warn!("failed to open all detected devices; pipeline idle");
-        loop {
-            thread::sleep(Duration::from_secs(1));
+
+    fn snapshot(&self) -> (u64, ProfileSet) {
+        let p = self.profile.lock().unwrap();
+        (self.generation.load(Ordering::SeqCst), p.clone())
+    }
+
+    fn newer_than(&self, seen: u64) -> Option<(u64, ProfileSet)> {
+        if self.generation.load(Ordering::SeqCst) == seen {
+            return None;
         }
+        Some(self.snapshot())
+    }
+
+    fn mark_applied(&self, generation: u64) {
+        *self.applied.lock().unwrap() = generation;
+        self.adopted.notify_all();
     }
+}
+
+/// Poller token of the wake fd; the timer is 1, devices count up from 2.
+const WAKE_TOKEN: u64 = 0;
+const TIMER_TOKEN: u64 = 1;
+
+pub fn run_pipeline(
+    profile: Arc<ProfileHandle>,
+    enabled: Arc<AtomicBool>,
+    tx_evt: WakingSender<DaemonEvent>,
+) -> Result<()> {
+    // the pipeline works off its own copy, swapped only at frame boundaries
+    let (mut seen_gen, mut live) = profile.snapshot();
+    profile.mark_applied(seen_gen);
 
-    let mut tracker = Tracker::new();
-    let th = { profile.lock().unwrap().thresholds.clone() };
-    let mut detector = GestureDetector::new(th);
-    let mut sink = UinputSink::new().unwrap_or_else(|_| UinputSink::noop());
-    let mut prev_frame: Option<FrameSummary> = None;
+    let waker = profile.waker.clone();
+    let mut timer = Timer::new()?;
+    let mut devices = Devices {
+        ctxs: BTreeMap::new(),
+        poller: Poller::new()?,
+        next_token: TIMER_TOKEN + 1,
+        tx_evt,
+    };
+    devices.poller.add(waker.raw_fd(), WAKE_TOKEN)?;
+    devices.poller.add(timer.raw_fd(), TIMER_TOKEN)?;
 
-    let mut grabbed = false;
-    let mut scroll_acc: f32 = 0.0;
-    let mut cur_slot: i32 = 0;
-    let mut want_grab_next: Option<bool>;
+    // watch before scanning so a device plugged in meanwhile isn't missed
+    let hotplug = {
+        let waker = waker.clone();
+        match input::watch_input_dir(move || waker.wake()) {
+            Ok(w) => Some(w),
+            Err(e) => {
+                warn!("cannot watch /dev/input ({e}); devices are only scanned at startup");
+                None
+            }
+        }
+    };
+
+    for d in input::discover_multitouch() {
+        devices.add(&d, &live);
+    }
+    if devices.ctxs.is_empty() {
+        warn!("no multitouch devices yet; waiting for one to be plugged in");
+    }
+
+    let mut sink = UinputSink::with_wheel_mode(live.global.scroll.wheel)
+        .unwrap_or_else(|_| UinputSink::noop());
+    let runner = CommandRunner::new();
+    let mut ready = Vec::new();
 
     loop {
-        let mut any_event = false;
-        want_grab_next = None;
-
-        for dev in devs.iter_mut() {
-            if let Ok(events) = dev.fetch_events() {
-                for ev in events {
-                    any_event = true;
-
-                    if ev.event_type() == EventType::ABSOLUTE {
-                        match ev.code() {
-                            c if c == AbsoluteAxisCode::ABS_MT_SLOT.0 => {
-                                cur_slot = ev.value();
-                                tracker.on_slot(cur_slot);
-                            }
-                            c if c == AbsoluteAxisCode::ABS_MT_TRACKING_ID.0 => {
-                                tracker.on_tracking_id(ev.value());
-                            }
-                            c if c == AbsoluteAxisCode::ABS_MT_POSITION_X.0 => {
-                                tracker.on_pos_x(ev.value());
-                            }
-                            c if c == AbsoluteAxisCode::ABS_MT_POSITION_Y.0 => {
-                                tracker.on_pos_y(ev.value());
-                            }
-                            _ => {}
+        // sleep until input, a device deadline, or a wake-up from another thread
+        let deadline = devices
+            .ctxs
+            .values()
+            .filter_map(DeviceCtx::next_deadline)
+            .min();
+        timer.set(deadline)?;
+        devices.poller.wait(&mut ready)?;
+        for &token in &ready {
+            match token {
+                WAKE_TOKEN => waker.drain(),
+                TIMER_TOKEN => timer.clear(),
+                _ => {}
+            }
+        }
+
+        if let Some((_, rx)) = &hotplug {
+            while let Ok(hp) = rx.try_recv() {
+                match hp {
+                    Hotplug::Added(p) => {
+                        let path = p.display().to_string();
+                        if devices.ctxs.contains_key(&path) {
+                            continue;
                         }
-                    } else if ev.event_type() == EventType::SYNCHRONIZATION {
-                        if ev.code() == SynchronizationCode::SYN_REPORT.0 {
-                            let frame = tracker.on_syn_report();
-
-                            // schedule grab/ungrab after loop
-                            want_grab_next = Some(frame.active_count >= 2);
-
-                            // continuous scroll
-                            if let Some(prev) = &prev_frame {
-                                if frame.active_count == 2 {
-                                    let th = { profile.lock().unwrap().thresholds.clone() };
-                                    let dspan = (frame.span - prev.span).abs();
-                                    let pinch_gate = 0.6 * th.pinch_step;
-
-                                    if dspan < pinch_gate {
-                                        let dy = frame.centroid.1 - prev.centroid.1;
-                                        const STEP_NORM: f32 = 0.010;
-                                        const GAIN: f32 = 1.0;
-
-                                        scroll_acc += dy;
-                                        let steps = ((scroll_acc / STEP_NORM) * GAIN) as i32;
-                                        if steps.abs() >= 1 {
-                                            if let Err(e) = sink.scroll_vertical(-steps) {
-                                                error!("scroll emit failed: {e}");
-                                            }
-                                            scroll_acc -= (steps as f32) * STEP_NORM / GAIN;
-                                        }
-                                    }
-                                } else {
-                                    scroll_acc = 0.0;
-                                }
-                            }
-
-                            if let Some(gesture) = detector.update(&frame, prev_frame.as_ref()) {
-                                if let Err(e) =
-                                    super::dispatch::dispatch_gesture(&gesture, &profile, &mut sink)
-                                {
-                                    error!("dispatch failed: {e}");
-                                }
-                            }
-                            prev_frame = Some(frame);
+                        match input::probe_multitouch(&p) {
+                            Ok(Some(d)) => devices.add(&d, &live),
+                            Ok(None) => {}
+                            // usually udev hasn't fixed permissions yet; its
+                            // chmod shows up as another Added
+                            Err(e) => debug!("{path} not openable yet: {e}"),
                         }
                     }
+                    Hotplug::Removed(p) => devices.remove(&p.display().to_string(), "removed"),
                 }
             }
         }
 
-        // apply grab/ungrab once
-        if let Some(want) = want_grab_next {
-            if want && !grabbed {
-                for d in devs.iter_mut() {
-                    let _ = d.grab();
+        // pick up enable/disable requests from IPC; release any grab when disabled
+        sink.set_enabled(enabled.load(Ordering::Relaxed));
+
+        let mut dead = vec![];
+        for ctx in devices.ctxs.values_mut() {
+            if !sink.is_enabled() {
+                ctx.release_grab();
+            }
+
+            // inertial scrolling keeps going between frames
+            ctx.tick_kinetic(&mut sink);
+
+            if ready.contains(&ctx.token) {
+                let dropped_before = ctx.syn_dropped();
+                let events: Vec<RawEvent> = match ctx.dev.fetch_events() {
+                    Ok(events) => events.map(|ev| RawEvent::from(&ev)).collect(),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => vec![],
+                    Err(e) => {
+                        // unplugged (ENODEV) or otherwise unusable
+                        dead.push((ctx.info.path.clone(), e.to_string()));
+                        continue;
+                    }
+                };
+                for ev in &events {
+                    // a hold that came due before this event fires first
+                    if let Some(gesture) = ctx.tick_hold(Some(ev)) {
+                        dispatch(ctx, &gesture, &enabled, &mut sink, &runner);
+                    }
+                    for gesture in ctx.feed(ev, &mut sink) {
+                        dispatch(ctx, &gesture, &enabled, &mut sink, &runner);
+                    }
+                    for phase in ctx.take_phases() {
+                        if let Err(e) = super::dispatch::dispatch_phase(
+                            &phase,
+                            &ctx.info,
+                            &ctx.profile,
+                            &enabled,
+                            &mut sink,
+                            &runner,
+                        ) {
+                            error!("dispatch failed for {}: {e}", ctx.info.path);
+                        }
+                    }
                 }
-                grabbed = true;
-                info!("grabbed touch devices (>=2 fingers)");
-            } else if !want && grabbed {
-                for d in devs.iter_mut() {
-                    let _ = d.ungrab();
+                if ctx.syn_dropped() > dropped_before {
+                    let _ = devices.tx_evt.send(DaemonEvent::SynDropped {
+                        path: ctx.info.path.clone(),
+                        count: ctx.syn_dropped() - dropped_before,
+                    });
                 }
-                grabbed = false;
-                info!("released touch devices (<2 fingers)");
             }
+
+            // holds fire on the timer, without a frame
+            if let Some(gesture) = ctx.tick_hold(None) {
+                dispatch(ctx, &gesture, &enabled, &mut sink, &runner);
+            }
+
+            ctx.apply_grab();
+        }
+        for (path, why) in dead {
+            devices.remove(&path, &why);
         }
 
-        if !any_event {
-            thread::sleep(Duration::from_millis(4));
+        // profile swaps wait until no device is halfway through a frame
+        if devices.ctxs.values().all(|c| !c.mid_frame())
+            && adopt_profile(&profile, &mut seen_gen, &mut live, &mut sink)
+        {
+            for ctx in devices.ctxs.values_mut() {
+                let (name, p) = live.for_device(&ctx.info);
+                if ctx.set_profile(name, p) {
+                    let _ = devices.tx_evt.send(DaemonEvent::DeviceProfile {
+                        path: ctx.info.path.clone(),
+                        profile: name.to_string(),
+                    });
+                }
+            }
+            devices.apply_rules(&live);
         }
     }
 }
+
+fn dispatch(
+    ctx: &DeviceCtx,
+    gesture: &Gesture,
+    enabled: &AtomicBool,
+    sink: &mut UinputSink,
+    runner: &CommandRunner,
+) {
+    debug!("{}: {gesture:?}", ctx.info.path);
+    if let Err(e) =
+        super::dispatch::dispatch_gesture(gesture, &ctx.info, &ctx.profile, enabled, sink, runner)
+    {
+        error!("dispatch failed for {}: {e}", ctx.info.path);
+    }
+}
+
+/// Open devices keyed by path, each registered with the poller.
+struct Devices {
+    ctxs: BTreeMap<String, DeviceCtx>,
+    poller: Poller,
+    next_token: u64,
+    tx_evt: WakingSender<DaemonEvent>,
+}
+
+impl Devices {
+    fn add(&mut self, info: &DeviceInfo, live: &ProfileSet) {
+        let verdict = live.global.devices.evaluate(info);
+        if !verdict.included() {
+            info!(
+                "ignoring {} ({}): excluded by {}",
+                info.name,
+                info.path,
+                match verdict.rule {
+                    Some(i) => format!("devices.rules[{i}]"),
+                    None => "devices.default".to_string(),
+                }
+            );
+            return;
+        }
+        let (name, profile) = live.for_device(info);
+        let ctx = DeviceCtx::open(info, self.next_token, name, profile).and_then(|ctx| {
+            self.poller.add(ctx.dev.as_raw_fd(), ctx.token)?;
+            Ok(ctx)
+        });
+        match ctx {
+            Ok(ctx) => {
+                self.next_token += 1;
+                let _ = self.tx_evt.send(DaemonEvent::DeviceAdded {
+                    path: info.path.clone(),
+                    description: ctx.describe(),
+                    profile: name.to_string(),
+                });
+                self.ctxs.insert(info.path.clone(), ctx);
+            }
+            Err(e) => {
+                let _ = self.tx_evt.send(DaemonEvent::Log(e.to_string()));
+            }
+        }
+    }
+
+    fn remove(&mut self, path: &str, reason: &str) {
+        // dropping the context closes the fd, which also ends any grab
+        if let Some(ctx) = self.ctxs.remove(path) {
+            let _ = self.poller.delete(ctx.dev.as_raw_fd());
+            let _ = self.tx_evt.send(DaemonEvent::DeviceRemoved {
+                path: path.to_string(),
+                reason: reason.to_string(),
+            });
+        }
+    }
+
+    /// Close devices the active profile's `[devices]` rules now exclude and
+    /// open the ones they now include.
+    fn apply_rules(&mut self, live: &ProfileSet) {
+        let excluded: Vec<String> = self
+            .ctxs
+            .values()
+            .filter(|c| !live.global.devices.evaluate(&c.info).included())
+            .map(|c| c.info.path.clone())
+            .collect();
+        for path in excluded {
+            self.remove(&path, "excluded by profile");
+        }
+        for d in input::discover_multitouch() {
+            if !self.ctxs.contains_key(&d.path) {
+                self.add(&d, live);
+            }
+        }
+    }
+}
+
+/// Swap in a newly published profile, if any. Callers must be between frames
+/// and hand every device its profile again when this returns true.
+fn adopt_profile(
+    handle: &ProfileHandle,
+    seen_gen: &mut u64,
+    live: &mut ProfileSet,
+    sink: &mut UinputSink,
+) -> bool {
+    let Some((generation, p)) = handle.newer_than(*seen_gen) else {
+        return false;
+    };
+    // there's one virtual device, so the wheel mode follows the active profile
+    if let Err(e) = sink.set_wheel_mode(p.global.scroll.wheel) {
+        error!("failed to switch wheel mode: {e}");
+    }
+    *live = p;
+    *seen_gen = generation;
+    handle.mark_applied(generation);
+    info!("pipeline: adopted profile generation {generation}");
+    true
+}
@@ -0,0 +1,248 @@
+//! Minimal epoll, timerfd and eventfd wrappers for the pipeline loop.
+//!
+//! The pipeline sleeps in `epoll_wait` until a device has input, a timer
+//! deadline passes (kinetic scrolling, time-based gestures), or another
+//! thread wakes it (profile publish, enable/disable, hotplug).
+
+use std::{
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    sync::{
+        Arc,
+        mpsc::{SendError, Sender},
+    },
+    time::{Duration, Instant},
+};
+
+fn cvt(ret: libc::c_int) -> io::Result<libc::c_int> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+pub struct Poller {
+    epfd: OwnedFd,
+    events: Vec<libc::epoll_event>,
+}
+
+impl Poller {
+    pub fn new() -> io::Result<Self> {
+        let fd = cvt(unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) })?;
+        Ok(Self {
+            epfd: unsafe { OwnedFd::from_raw_fd(fd) },
+            events: vec![libc::epoll_event { events: 0, u64: 0 }; 32],
+        })
+    }
+
+    /// Watch `fd` for input (level-triggered); it is reported as `token`.
+    pub fn add(&self, fd: RawFd, token: u64) -> io::Result<()> {
+        let mut ev = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: token,
+        };
+        cvt(unsafe { libc::epoll_ctl(self.epfd.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, &mut ev) })?;
+        Ok(())
+    }
+
+    pub fn delete(&self, fd: RawFd) -> io::Result<()> {
+        cvt(unsafe {
+            libc::epoll_ctl(
+                self.epfd.as_raw_fd(),
+                libc::EPOLL_CTL_DEL,
+                fd,
+                std::ptr::null_mut(),
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Block until something is ready and put the ready tokens in `ready`.
+    pub fn wait(&mut self, ready: &mut Vec<u64>) -> io::Result<()> {
+        ready.clear();
+        let n = loop {
+            let n = unsafe {
+                libc::epoll_wait(
+                    self.epfd.as_raw_fd(),
+                    self.events.as_mut_ptr(),
+                    self.events.len() as libc::c_int,
+                    -1,
+                )
+            };
+            match cvt(n) {
+                Ok(n) => break n as usize,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        ready.extend(self.events[..n].iter().map(|e| e.u64));
+        Ok(())
+    }
+}
+
+/// One-shot CLOCK_MONOTONIC timer.
+pub struct Timer {
+    fd: OwnedFd,
+    armed: Option<Instant>,
+}
+
+impl Timer {
+    pub fn new() -> io::Result<Self> {
+        let fd = cvt(unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        })?;
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            armed: None,
+        })
+    }
+
+    pub fn raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    /// Fire at `deadline` (immediately if it has passed), or never for `None`.
+    pub fn set(&mut self, deadline: Option<Instant>) -> io::Result<()> {
+        if deadline == self.armed {
+            return Ok(());
+        }
+        let value = match deadline {
+            Some(d) => {
+                // a zero it_value disarms, so fire after at least 1 ns
+                let left = d
+                    .saturating_duration_since(Instant::now())
+                    .max(Duration::from_nanos(1));
+                libc::timespec {
+                    tv_sec: left.as_secs() as libc::time_t,
+                    tv_nsec: left.subsec_nanos() as libc::c_long,
+                }
+            }
+            None => libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+        };
+        let spec = libc::itimerspec {
+            it_interval: libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: value,
+        };
+        cvt(unsafe { libc::timerfd_settime(self.fd.as_raw_fd(), 0, &spec, std::ptr::null_mut()) })?;
+        self.armed = deadline;
+        Ok(())
+    }
+
+    /// Acknowledge an expiry.
+    pub fn clear(&mut self) {
+        let mut buf = [0u8; 8];
+        let _ = unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+        self.armed = None;
+    }
+}
+
+/// Lets other threads interrupt [`Poller::wait`].
+#[derive(Clone)]
+pub struct Waker {
+    fd: Arc<OwnedFd>,
+}
+
+impl Waker {
+    pub fn new() -> io::Result<Self> {
+        let fd = cvt(unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) })?;
+        Ok(Self {
+            fd: Arc::new(unsafe { OwnedFd::from_raw_fd(fd) }),
+        })
+    }
+
+    pub fn raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    pub fn wake(&self) {
+        let one = 1u64.to_ne_bytes();
+        let _ = unsafe { libc::write(self.fd.as_raw_fd(), one.as_ptr().cast(), one.len()) };
+    }
+
+    /// Reset after waking up.
+    pub fn drain(&self) {
+        let mut buf = [0u8; 8];
+        let _ = unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+    }
+}
+
+/// A channel sender that also wakes the receiving thread's [`Poller`], so
+/// the receiver can sleep in `epoll_wait` instead of polling `try_recv`.
+pub struct WakingSender<T> {
+    tx: Sender<T>,
+    waker: Waker,
+}
+
+impl<T> Clone for WakingSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            waker: self.waker.clone(),
+        }
+    }
+}
+
+impl<T> WakingSender<T> {
+    pub fn new(tx: Sender<T>, waker: Waker) -> Self {
+        Self { tx, waker }
+    }
+
+    pub fn send(&self, msg: T) -> Result<(), SendError<T>> {
+        self.tx.send(msg)?;
+        self.waker.wake();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waker_and_timer_wake_the_poller() {
+        let mut poller = Poller::new().unwrap();
+        let waker = Waker::new().unwrap();
+        let mut timer = Timer::new().unwrap();
+        poller.add(waker.raw_fd(), 1).unwrap();
+        poller.add(timer.raw_fd(), 2).unwrap();
+        let mut ready = vec![];
+
+        let w = waker.clone();
+        std::thread::spawn(move || w.wake());
+        poller.wait(&mut ready).unwrap();
+        assert_eq!(ready, [1]);
+        waker.drain();
+
+        let start = Instant::now();
+        timer.set(Some(start + Duration::from_millis(20))).unwrap();
+        poller.wait(&mut ready).unwrap();
+        assert_eq!(ready, [2]);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        timer.clear();
+    }
+
+    #[test]
+    fn waking_sender_wakes_the_receiver() {
+        let mut poller = Poller::new().unwrap();
+        let waker = Waker::new().unwrap();
+        poller.add(waker.raw_fd(), 7).unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let tx = WakingSender::new(tx, waker.clone());
+
+        std::thread::spawn(move || tx.send(42).unwrap());
+        let mut ready = vec![];
+        poller.wait(&mut ready).unwrap();
+        assert_eq!(ready, [7]);
+        assert_eq!(rx.recv().unwrap(), 42);
+    }
+}
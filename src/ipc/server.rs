@@ -1,15 +1,25 @@
 use anyhow::Result;
-use log::{error, info};
+use log::{error, info, warn};
 use std::{
-    io::{BufRead, BufReader, Write},
-    os::unix::net::{UnixListener, UnixStream},
+    collections::{BTreeMap, VecDeque},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    os::{
+        fd::AsRawFd,
+        unix::net::{UnixListener, UnixStream},
+    },
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
     thread,
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use super::pipeline::run_pipeline;
+use super::pipeline::{ProfileHandle, run_pipeline};
+use super::poll::{Poller, Waker, WakingSender};
 use super::runtime::socket_path;
-use crate::config::{DaemonConfigState, Profile};
+use super::watch::spawn_profile_watcher;
+use crate::config::{Assignment, DaemonConfigState, Profile, ProfileSet, profile_diff};
 
 pub fn run_daemon() -> Result<()> {
     // socket
@@ -24,71 +34,242 @@ pub fn run_daemon() -> Result<()> {
     let mut state = DaemonState::new()?;
     info!("daemon: active profile '{}'", state.cfg.active_name);
 
-    // channels
+    // channels; senders wake the main loop's poller
+    let inbox = Waker::new()?;
     let (tx_req, rx_req) = std::sync::mpsc::channel::<IpcMsg>();
+    let tx_req = WakingSender::new(tx_req, inbox.clone());
     let (tx_evt, rx_evt) = std::sync::mpsc::channel::<DaemonEvent>();
+    let tx_evt = WakingSender::new(tx_evt, inbox.clone());
 
     // gesture thread
-    let mut gesture_thread = GestureThread::start(state.cfg.profile.clone(), tx_evt.clone())?;
+    let gesture_thread = GestureThread::start(
+        state.cfg.profile_set(),
+        state.enabled.clone(),
+        state.waker.clone(),
+        tx_evt.clone(),
+    )?;
 
-    // accept loop
+    // pick up edits to profiles/ and the active pointer
+    if let Err(e) = spawn_profile_watcher(
+        state.cfg.config_dir.clone(),
+        state.cfg.profiles_dir.clone(),
+        vec![
+            state.cfg.active_ptr.clone(),
+            state.cfg.assignments_path.clone(),
+        ],
+        tx_evt.clone(),
+    ) {
+        warn!("profile watcher unavailable, use 'touchctl reload': {e}");
+    }
+
+    // main loop: sleeps until a client connects or a channel has mail
     listener.set_nonblocking(true)?;
+    let mut poller = Poller::new()?;
+    poller.add(listener.as_raw_fd(), LISTEN_TOKEN)?;
+    poller.add(inbox.raw_fd(), INBOX_TOKEN)?;
+    let mut ready = Vec::new();
     loop {
-        match listener.accept() {
-            Ok((stream, _)) => {
-                let tx = tx_req.clone();
-                let st_snapshot = state.clone_shallow();
-                let tx_evt_clone = tx_evt.clone();
-                thread::spawn(move || {
-                    if let Err(e) = handle_client(stream, st_snapshot, tx, tx_evt_clone) {
-                        error!("ipc client error: {e}");
-                    }
-                });
-            }
-            Err(_) => {}
+        poller.wait(&mut ready)?;
+        if ready.contains(&INBOX_TOKEN) {
+            inbox.drain();
+        }
+
+        loop {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("accept failed: {e}");
+                    break;
+                }
+            };
+            // the listener is non-blocking; clients are served blocking
+            stream.set_nonblocking(false)?;
+            let tx = tx_req.clone();
+            let st_snapshot = state.clone_shallow();
+            thread::spawn(move || {
+                if let Err(e) = handle_client(stream, st_snapshot, tx) {
+                    error!("ipc client error: {e}");
+                }
+            });
         }
 
         while let Ok(evt) = rx_evt.try_recv() {
-            if let DaemonEvent::Log(s) = evt {
-                info!("[gesture] {s}");
+            match evt {
+                DaemonEvent::Log(s) => info!("[gesture] {s}"),
+                DaemonEvent::DeviceAdded {
+                    path,
+                    description,
+                    profile,
+                } => {
+                    info!("device added: {description}, profile '{profile}'");
+                    state.note_hotplug("added", &path, &description);
+                    state.devices.insert(
+                        path.clone(),
+                        serde_json::json!({
+                            "path": path,
+                            "description": description,
+                            "profile": profile,
+                            "syn_dropped": 0,
+                        }),
+                    );
+                }
+                DaemonEvent::DeviceProfile { path, profile } => {
+                    info!("{path} now uses profile '{profile}'");
+                    if let Some(d) = state.devices.get_mut(&path) {
+                        d["profile"] = profile.into();
+                    }
+                }
+                DaemonEvent::SynDropped { path, count } => {
+                    state.syn_dropped += count;
+                    if let Some(d) = state.devices.get_mut(&path) {
+                        d["syn_dropped"] = (d["syn_dropped"].as_u64().unwrap_or(0) + count).into();
+                    }
+                }
+                DaemonEvent::DeviceRemoved { path, reason } => {
+                    info!("device removed: {path} ({reason})");
+                    state.note_hotplug("removed", &path, &reason);
+                    state.devices.remove(&path);
+                }
+                DaemonEvent::ProfilesChanged => {
+                    let old = state.cfg.profile.clone();
+                    let old_name = state.cfg.active_name.clone();
+                    let old_assignments = state.cfg.assignments.clone();
+                    match state.cfg.sync_with_disk() {
+                        Err(e) => {
+                            warn!("auto-reload failed, keeping last good profile: {e}");
+                            state.cfg.last_error = Some(e.to_string());
+                        }
+                        Ok(()) => {
+                            state.cfg.last_error = None;
+                            if state.cfg.active_name != old_name
+                                || !profile_diff(&old, &state.cfg.profile).is_empty()
+                                || assignments_differ(&old_assignments, &state.cfg.assignments)
+                            {
+                                let resp = apply_profile_change(&state, &gesture_thread, &old);
+                                info!(
+                                    "auto-reloaded profile '{}': {}",
+                                    state.cfg.active_name, resp["data"]["changed"]
+                                );
+                            }
+                        }
+                    }
+                }
             }
         }
 
         while let Ok(msg) = rx_req.try_recv() {
             match msg {
-                IpcMsg::Reload => {
-                    if let Err(e) = state.cfg.reload() {
-                        error!("reload failed: {e}");
-                    } else {
-                        let new_prof = state.cfg.profile.clone();
-                        gesture_thread.update_profile(new_prof);
-                        info!("profile reloaded");
-                    }
+                IpcMsg::Reload(reply) => {
+                    let old = state.cfg.profile.clone();
+                    let resp = match state.cfg.reload() {
+                        Err(e) => {
+                            error!("reload failed: {e}");
+                            state.cfg.last_error = Some(e.to_string());
+                            serde_json::json!({"ok": false, "error": format!("reload failed: {e}")})
+                        }
+                        Ok(()) => {
+                            info!("profile reloaded");
+                            state.cfg.last_error = None;
+                            apply_profile_change(&state, &gesture_thread, &old)
+                        }
+                    };
+                    let _ = reply.send(resp);
                 }
-                IpcMsg::UseProfile(name) => {
-                    if let Err(e) = state.cfg.set_active(&name) {
-                        error!("use profile failed: {e}");
-                    } else {
-                        let new_prof = state.cfg.profile.clone();
-                        gesture_thread.update_profile(new_prof);
-                        info!("switched active profile to {}", state.cfg.active_name);
-                    }
+                IpcMsg::UseProfile(name, Some(device), reply) => {
+                    let resp = match state.cfg.assign(&name, &device) {
+                        Err(e) => {
+                            error!("use profile failed: {e}");
+                            serde_json::json!({"ok": false, "error": format!("use profile failed: {e}")})
+                        }
+                        Ok(()) => {
+                            info!("devices matching '{device}' now use profile {name}");
+                            let applied = publish_profiles(&state, &gesture_thread);
+                            serde_json::json!({"ok": true, "data": {
+                                "device": device,
+                                "profile": name,
+                                "applied": applied,
+                            }})
+                        }
+                    };
+                    let _ = reply.send(resp);
+                }
+                IpcMsg::UseProfile(name, None, reply) => {
+                    let old = state.cfg.profile.clone();
+                    let resp = match state.cfg.set_active(&name) {
+                        Err(e) => {
+                            error!("use profile failed: {e}");
+                            serde_json::json!({"ok": false, "error": format!("use profile failed: {e}")})
+                        }
+                        Ok(()) => {
+                            info!("switched active profile to {}", state.cfg.active_name);
+                            state.cfg.last_error = None;
+                            apply_profile_change(&state, &gesture_thread, &old)
+                        }
+                    };
+                    let _ = reply.send(resp);
                 }
                 IpcMsg::Shutdown => {
                     return Ok(());
                 }
             }
         }
+    }
+}
 
-        thread::sleep(Duration::from_millis(5));
+/// Poller tokens of the daemon's main loop.
+const LISTEN_TOKEN: u64 = 0;
+const INBOX_TOKEN: u64 = 1;
+
+/// Hand the freshly loaded profile to the pipeline and report what changed
+/// and whether the pipeline has picked it up yet.
+fn apply_profile_change(
+    state: &DaemonState,
+    gesture_thread: &GestureThread,
+    old: &Profile,
+) -> serde_json::Value {
+    let changed = profile_diff(old, &state.cfg.profile);
+    let applied = publish_profiles(state, gesture_thread);
+    serde_json::json!({"ok": true, "data": {
+        "active_profile": state.cfg.active_name,
+        "changed": changed,
+        "applied": applied,
+    }})
+}
+
+/// Publish the active profile and device assignments; true once the
+/// pipeline has adopted them.
+fn publish_profiles(state: &DaemonState, gesture_thread: &GestureThread) -> bool {
+    let generation = gesture_thread.update_profile(state.cfg.profile_set());
+    gesture_thread
+        .profile
+        .wait_applied(generation, Duration::from_millis(250))
+}
+
+fn assignments_differ(a: &[(Assignment, Profile)], b: &[(Assignment, Profile)]) -> bool {
+    a.len() != b.len()
+        || a.iter()
+            .zip(b)
+            .any(|((xa, xp), (ya, yp))| xa != ya || !profile_diff(xp, yp).is_empty())
+}
+
+/// Forward a request to the main loop and wait for its reply.
+fn round_trip(
+    tx_req: &WakingSender<IpcMsg>,
+    msg: impl FnOnce(std::sync::mpsc::Sender<serde_json::Value>) -> IpcMsg,
+) -> serde_json::Value {
+    let (tx, rx) = std::sync::mpsc::channel();
+    if tx_req.send(msg(tx)).is_err() {
+        return serde_json::json!({"ok": false, "error": "daemon is shutting down"});
     }
+    rx.recv_timeout(Duration::from_secs(2))
+        .unwrap_or_else(|_| serde_json::json!({"ok": false, "error": "daemon did not reply"}))
 }
 
 fn handle_client(
     mut stream: UnixStream,
-    mut st: DaemonState,
-    tx_req: std::sync::mpsc::Sender<IpcMsg>,
-    _tx_evt: std::sync::mpsc::Sender<DaemonEvent>,
+    st: DaemonState,
+    tx_req: WakingSender<IpcMsg>,
 ) -> Result<()> {
     let mut reader = BufReader::new(stream.try_clone()?);
     let mut line = String::new();
@@ -101,19 +282,35 @@ fn handle_client(
 
     let resp = match op {
         "status" => serde_json::json!({"ok": true, "data": {
-            "enabled": st.enabled,
+            "enabled": st.enabled.load(Ordering::Relaxed),
             "active_profile": st.cfg.active_name,
             "socket": super::runtime::socket_path(),
-            "devices": st.cfg.detected_devices,
+            "devices": st.devices.values().collect::<Vec<_>>(),
+            "hotplug": st.hotplug,
+            "syn_dropped": st.syn_dropped,
+            "profile_error": st.cfg.last_error,
         }}),
-        "reload" => {
-            let _ = tx_req.send(IpcMsg::Reload);
-            serde_json::json!({"ok": true, "data": {"active_profile": st.cfg.active_name}})
+        "enable" | "disable" | "toggle" => {
+            let now = match op {
+                "enable" | "disable" => {
+                    let now = op == "enable";
+                    st.enabled.store(now, Ordering::Relaxed);
+                    now
+                }
+                // a gesture bound to `toggle` may flip it concurrently
+                _ => !st.enabled.fetch_xor(true, Ordering::Relaxed),
+            };
+            st.waker.wake();
+            info!("{}", if now { "enabled" } else { "disabled" });
+            serde_json::json!({"ok": true, "data": {"enabled": now}})
         }
+        "reload" => round_trip(&tx_req, IpcMsg::Reload),
         "use" => {
             let name = req.get("profile").and_then(|v| v.as_str()).unwrap_or("");
-            let _ = tx_req.send(IpcMsg::UseProfile(name.to_string()));
-            serde_json::json!({"ok": true, "data": {"active_profile": name}})
+            let device = req.get("device").and_then(|v| v.as_str()).map(String::from);
+            round_trip(&tx_req, |reply| {
+                IpcMsg::UseProfile(name.to_string(), device, reply)
+            })
         }
         "list" => {
             let list = st.cfg.list_profiles();
@@ -125,9 +322,9 @@ fn handle_client(
         }
         "shutdown" => {
             let _ = tx_req.send(IpcMsg::Shutdown);
-            let _ = write!(
+            let _ = writeln!(
                 stream,
-                "{}\n",
+                "{}",
                 serde_json::json!({"ok": true, "data": "shutting down"})
             );
             std::process::exit(0);
@@ -135,48 +332,120 @@ fn handle_client(
         _ => serde_json::json!({"ok": false, "error": format!("unknown op: {op}")}),
     };
 
-    write!(stream, "{}\n", resp)?;
+    writeln!(stream, "{}", resp)?;
     Ok(())
 }
 
+/// How many hotplug events `status` remembers.
+const HOTPLUG_HISTORY: usize = 16;
+
 struct DaemonState {
-    pub enabled: bool,
+    /// Shared with the gesture pipeline; gestures and IPC both flip it.
+    pub enabled: Arc<AtomicBool>,
+    /// Interrupts the pipeline's poll after `enabled` changes.
+    pub waker: Waker,
     pub cfg: DaemonConfigState,
+    /// Devices the pipeline has open, by path.
+    pub devices: BTreeMap<String, serde_json::Value>,
+    /// Most recent hotplug events, oldest first.
+    pub hotplug: VecDeque<serde_json::Value>,
+    /// SYN_DROPPED resyncs across all devices since startup.
+    pub syn_dropped: u64,
 }
 
 impl DaemonState {
     fn new() -> Result<Self> {
         let cfg = DaemonConfigState::load_or_install_default()?;
-        Ok(Self { enabled: true, cfg })
+        Ok(Self {
+            enabled: Arc::new(AtomicBool::new(true)),
+            waker: Waker::new()?,
+            cfg,
+            devices: BTreeMap::new(),
+            hotplug: VecDeque::new(),
+            syn_dropped: 0,
+        })
     }
     fn clone_shallow(&self) -> Self {
         Self {
-            enabled: self.enabled,
+            enabled: self.enabled.clone(),
+            waker: self.waker.clone(),
             cfg: self.cfg.clone(),
+            devices: self.devices.clone(),
+            hotplug: self.hotplug.clone(),
+            syn_dropped: self.syn_dropped,
+        }
+    }
+    fn note_hotplug(&mut self, what: &str, path: &str, detail: &str) {
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        if self.hotplug.len() == HOTPLUG_HISTORY {
+            self.hotplug.pop_front();
         }
+        self.hotplug.push_back(serde_json::json!({
+            "event": what,
+            "path": path,
+            "detail": detail,
+            "at": at,
+        }));
     }
 }
 
 enum IpcMsg {
-    Reload,
-    UseProfile(String),
+    Reload(std::sync::mpsc::Sender<serde_json::Value>),
+    /// Profile name, and the device match when assigning to one device.
+    UseProfile(
+        String,
+        Option<String>,
+        std::sync::mpsc::Sender<serde_json::Value>,
+    ),
     Shutdown,
 }
 pub enum DaemonEvent {
     Log(String),
+    /// The pipeline opened a multitouch device.
+    DeviceAdded {
+        path: String,
+        description: String,
+        profile: String,
+    },
+    /// A profile change moved an open device to a different profile.
+    DeviceProfile {
+        path: String,
+        profile: String,
+    },
+    /// A device was unplugged or stopped working and has been closed.
+    DeviceRemoved {
+        path: String,
+        reason: String,
+    },
+    /// The kernel dropped `count` batches of a device's events; its touch
+    /// state was rebuilt from the device.
+    SynDropped {
+        path: String,
+        count: u64,
+    },
+    /// Something under the profiles directory or the active pointer changed.
+    ProfilesChanged,
 }
 
 struct GestureThread {
-    profile: std::sync::Arc<std::sync::Mutex<Profile>>,
+    profile: Arc<ProfileHandle>,
     _thread: thread::JoinHandle<()>,
 }
 
 impl GestureThread {
-    fn start(profile: Profile, tx_evt: std::sync::mpsc::Sender<DaemonEvent>) -> Result<Self> {
-        let profile_arc = std::sync::Arc::new(std::sync::Mutex::new(profile));
+    fn start(
+        profile: ProfileSet,
+        enabled: Arc<AtomicBool>,
+        waker: Waker,
+        tx_evt: WakingSender<DaemonEvent>,
+    ) -> Result<Self> {
+        let profile_arc = Arc::new(ProfileHandle::new(profile, waker));
         let prof_clone = profile_arc.clone();
         let handle = thread::spawn(move || {
-            if let Err(e) = run_pipeline(prof_clone, tx_evt) {
+            if let Err(e) = run_pipeline(prof_clone, enabled, tx_evt) {
                 error!("gesture pipeline failed: {e}");
             }
         });
@@ -185,10 +454,9 @@ impl GestureThread {
             _thread: handle,
         })
     }
-    fn update_profile(&mut self, new_profile: Profile) {
-        if let Ok(mut p) = self.profile.lock() {
-            *p = new_profile;
-        }
+    /// Publish a new profile; the pipeline adopts it at the next frame boundary.
+    fn update_profile(&self, new_profile: ProfileSet) -> u64 {
+        self.profile.publish(new_profile)
     }
 }
 
@@ -0,0 +1,81 @@
+//! Watches the profiles directory, the `active` pointer and the device
+//! assignments so edits made outside of touchctl (editors, dotfile tooling)
+//! are picked up without a manual `touchctl reload`.
+
+use anyhow::Result;
+use log::{debug, warn};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use super::poll::WakingSender;
+use super::server::DaemonEvent;
+
+/// Editors save in bursts (write temp, rename, chmod, ...); wait for the
+/// directory to be quiet this long before reloading.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Start watching `config_dir` (for `files` such as the `active` pointer) and
+/// `profiles_dir`. Each burst of relevant changes results in one
+/// `DaemonEvent::ProfilesChanged`.
+pub fn spawn_profile_watcher(
+    config_dir: PathBuf,
+    profiles_dir: PathBuf,
+    files: Vec<PathBuf>,
+    tx_evt: WakingSender<DaemonEvent>,
+) -> Result<()> {
+    let (tx, rx) = mpsc::channel::<notify::Result<notify::Event>>();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&config_dir, RecursiveMode::NonRecursive)?;
+    watcher.watch(&profiles_dir, RecursiveMode::NonRecursive)?;
+
+    let relevant = move |ev: &notify::Event| {
+        !matches!(ev.kind, EventKind::Access(_))
+            && ev
+                .paths
+                .iter()
+                .any(|p| is_profile_path(p, &profiles_dir, &files))
+    };
+
+    thread::Builder::new()
+        .name("profile watcher".into())
+        .spawn(move || {
+            // keep the watcher alive for as long as this thread runs
+            let _watcher = watcher;
+            loop {
+                // block until something we care about changes
+                match rx.recv() {
+                    Ok(Ok(ev)) if relevant(&ev) => debug!("profile watcher: {ev:?}"),
+                    Ok(Ok(_)) => continue,
+                    Ok(Err(e)) => {
+                        warn!("profile watcher: {e}");
+                        continue;
+                    }
+                    Err(_) => return,
+                }
+                // then swallow the rest of the burst
+                loop {
+                    match rx.recv_timeout(DEBOUNCE) {
+                        Ok(_) => continue,
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                if tx_evt.send(DaemonEvent::ProfilesChanged).is_err() {
+                    return;
+                }
+            }
+        })?;
+    Ok(())
+}
+
+fn is_profile_path(p: &Path, profiles_dir: &Path, files: &[PathBuf]) -> bool {
+    if files.iter().any(|f| f == p) {
+        return true;
+    }
+    p.parent() == Some(profiles_dir) && p.extension().is_some_and(|e| e == "toml")
+}
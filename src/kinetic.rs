@@ -0,0 +1,177 @@
+//! Inertial scrolling after a two-finger scroll is released.
+//!
+//! While fingers scroll, the pipeline feeds centroid samples in. On lift the
+//! release velocity is estimated from the most recent samples and, if fast
+//! enough, the scroll keeps coasting with exponential friction until it drops
+//! below `kinetic_min_velocity` or a new touch cancels it.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::config::Thresholds;
+
+/// Only samples this close to the last one contribute to the release velocity.
+const VELOCITY_WINDOW_MS: u128 = 80;
+/// Fingers that rested this long before lifting don't fling.
+const MAX_RELEASE_GAP_MS: u128 = 50;
+/// Need at least this much time between samples for a stable estimate.
+const MIN_SAMPLE_SPAN_MS: u128 = 8;
+
+#[derive(Debug, Default)]
+pub struct Kinetic {
+    samples: VecDeque<(u128, (f32, f32))>,
+    /// Normalized units per second while coasting.
+    velocity: Option<(f32, f32)>,
+}
+
+impl Kinetic {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the centroid of a two-finger scroll frame.
+    pub fn sample(&mut self, t_ms: u128, centroid: (f32, f32)) {
+        self.velocity = None;
+        self.samples.push_back((t_ms, centroid));
+        while let Some(&(t0, _)) = self.samples.front() {
+            if t_ms.saturating_sub(t0) > VELOCITY_WINDOW_MS {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Fingers lifted at `t_ms`: start coasting if the release was fast enough.
+    pub fn release(&mut self, t_ms: u128, th: &Thresholds) -> bool {
+        let samples = std::mem::take(&mut self.samples);
+        self.velocity = None;
+
+        let (Some(&(t0, c0)), Some(&(t1, c1))) = (samples.front(), samples.back()) else {
+            return false;
+        };
+        // samples out of time order span no time and give no estimate
+        if t_ms.saturating_sub(t1) > MAX_RELEASE_GAP_MS
+            || t1.saturating_sub(t0) < MIN_SAMPLE_SPAN_MS
+        {
+            return false;
+        }
+        let dt = (t1 - t0) as f32 / 1000.0;
+        let v = ((c1.0 - c0.0) / dt, (c1.1 - c0.1) / dt);
+        if th.dist(v.0, v.1) < th.kinetic_min_velocity {
+            return false;
+        }
+        self.velocity = Some(v);
+        true
+    }
+
+    /// A new touch landed (or scrolling stopped for another reason).
+    pub fn cancel(&mut self) {
+        self.samples.clear();
+        self.velocity = None;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.velocity.is_some()
+    }
+
+    /// Advance the coast by `dt`; returns the centroid travel to scroll by,
+    /// or `None` once the motion has died out.
+    pub fn tick(&mut self, dt: Duration, th: &Thresholds) -> Option<(f32, f32)> {
+        let v = self.velocity?;
+        let dt = dt.as_secs_f32();
+        let decay = (-th.kinetic_friction * dt).exp();
+        let nv = (v.0 * decay, v.1 * decay);
+        if th.dist(nv.0, nv.1) < th.kinetic_min_velocity {
+            self.velocity = None;
+            return None;
+        }
+        self.velocity = Some(nv);
+        // travel under exponential decay over dt, i.e. the integral of v(t)
+        let k = if th.kinetic_friction > 0.0 {
+            (1.0 - decay) / th.kinetic_friction
+        } else {
+            dt
+        };
+        Some((v.0 * k, v.1 * k))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::th;
+
+    fn flick(k: &mut Kinetic, per_10ms: f32) {
+        for i in 0..=5u128 {
+            k.sample(i * 10, (0.5, 0.2 + per_10ms * i as f32));
+        }
+    }
+
+    #[test]
+    fn fast_release_coasts_in_the_same_direction() {
+        let th = th();
+        let mut k = Kinetic::new();
+        flick(&mut k, 0.02); // 2.0 units/s downwards
+        assert!(k.release(55, &th));
+        let (dx, dy) = k.tick(Duration::from_millis(10), &th).unwrap();
+        assert_eq!(dx, 0.0);
+        assert!(dy > 0.0 && dy < 0.02);
+    }
+
+    #[test]
+    fn slow_release_does_not_coast() {
+        let th = th();
+        let mut k = Kinetic::new();
+        flick(&mut k, 0.0005);
+        assert!(!k.release(55, &th));
+        assert!(!k.is_active());
+    }
+
+    #[test]
+    fn resting_before_lift_does_not_coast() {
+        let th = th();
+        let mut k = Kinetic::new();
+        flick(&mut k, 0.02);
+        assert!(!k.release(50 + MAX_RELEASE_GAP_MS + 1, &th));
+    }
+
+    #[test]
+    fn friction_brings_it_to_rest() {
+        let th = th();
+        let mut k = Kinetic::new();
+        flick(&mut k, 0.02);
+        assert!(k.release(50, &th));
+        let mut travel = 0.0;
+        let mut ticks = 0;
+        while let Some((_, dy)) = k.tick(Duration::from_millis(4), &th) {
+            travel += dy;
+            ticks += 1;
+            assert!(ticks < 10_000, "never stopped");
+        }
+        assert!(!k.is_active());
+        // bounded by v0 / friction
+        assert!(travel > 0.0 && travel < 2.0 / th.kinetic_friction);
+    }
+
+    #[test]
+    fn samples_out_of_time_order_do_not_coast() {
+        let th = th();
+        let mut k = Kinetic::new();
+        k.sample(100, (0.5, 0.2));
+        k.sample(110, (0.5, 0.4));
+        k.sample(60, (0.5, 0.6));
+        assert!(!k.release(60, &th));
+        assert!(!k.is_active());
+    }
+
+    #[test]
+    fn cancel_stops_coasting() {
+        let th = th();
+        let mut k = Kinetic::new();
+        flick(&mut k, 0.02);
+        assert!(k.release(50, &th));
+        k.cancel();
+        assert!(k.tick(Duration::from_millis(4), &th).is_none());
+    }
+}
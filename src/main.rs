@@ -1,10 +1,15 @@
 mod actions;
 mod cli;
+mod command;
 mod config;
 mod gestures;
 mod input;
 mod ipc;
+mod kinetic;
 mod logging;
+mod replay;
+#[cfg(test)]
+mod testkit;
 mod tracker;
 
 fn main() -> anyhow::Result<()> {
@@ -0,0 +1,380 @@
+//! Record raw evdev sessions to disk and replay them offline.
+//!
+//! The recording format is plain text so captures can be diffed and checked
+//! into a regression corpus:
+//!
+//! ```text
+//! # touchctl-rec v1
+//! # device: SynPS/2 Synaptics TouchPad
+//! # path: /dev/input/event5
+//! # abs_x: 1266 5676 42
+//! # abs_y: 1096 4758 37
+//! # abs_pressure: 0 255 0
+//! # slots: 5
+//! <time_us> <type> <code> <value>
+//! ```
+//!
+//! Times are microseconds relative to the first recorded event. `abs_x` and
+//! `abs_y` hold the device's min, max and resolution for ABS_MT_POSITION_X/Y
+//! so replays normalize exactly like the live pipeline; `abs_pressure` is the
+//! same for ABS_MT_PRESSURE, when the device has it. `slots` is the MT slot
+//! count, 0 for protocol A devices. Lines starting with
+//! `#` are comments; unknown header keys are ignored.
+
+use anyhow::{Result, anyhow};
+use evdev::{Device, EventType, SynchronizationCode};
+use log::{info, warn};
+use std::{
+    fs,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::config::Thresholds;
+use crate::gestures::{Gesture, GestureDetector, PhaseEvent};
+use crate::input::{self, AbsAxis, Geometry, RawEvent};
+use crate::tracker::{FrameSummary, MAX_SLOTS, Tracker};
+
+const HEADER: &str = "# touchctl-rec v1";
+
+/// A gesture recognized during replay, stamped with its session time.
+#[derive(Debug, Clone)]
+pub struct ReplayHit {
+    pub t_ms: u128,
+    pub gesture: Gesture,
+}
+
+/// A phase of a continuous gesture seen during replay.
+#[derive(Debug, Clone)]
+pub struct ReplayPhase {
+    pub t_ms: u128,
+    pub event: PhaseEvent,
+}
+
+/// Everything a replay recognized, each list in time order.
+#[derive(Debug, Clone, Default)]
+pub struct Replay {
+    pub hits: Vec<ReplayHit>,
+    pub phases: Vec<ReplayPhase>,
+}
+
+/// A parsed recording.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    /// From the `abs_x`/`abs_y` headers; the default box if either is missing.
+    pub geometry: Geometry,
+    pub events: Vec<RawEvent>,
+}
+
+/// Capture events from `device` (or the first multitouch device) into `out`
+/// until SIGINT/SIGTERM. Returns the number of events written.
+pub fn record(out: &Path, device: Option<&str>) -> Result<usize> {
+    let path = match device {
+        Some(p) => p.to_string(),
+        None => input::discover_multitouch()
+            .into_iter()
+            .next()
+            .map(|d| d.path)
+            .ok_or_else(|| anyhow!("no multitouch devices detected"))?,
+    };
+    let mut dev = Device::open(&path).map_err(|e| anyhow!("failed to open {path}: {e}"))?;
+    dev.set_nonblocking(true)?;
+    if let Err(e) = input::use_monotonic_clock(&dev) {
+        warn!("{path}: cannot switch to CLOCK_MONOTONIC ({e}); times may jump");
+    }
+
+    let mut w = BufWriter::new(fs::File::create(out)?);
+    let geometry = dev.get_absinfo().ok().and_then(Geometry::from_absinfo);
+    write_header(
+        &mut w,
+        dev.name().unwrap_or("unknown"),
+        &path,
+        geometry.as_ref(),
+    )?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGINT, stop.clone())?;
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, stop.clone())?;
+    info!("recording {path} -> {} (Ctrl-C to stop)", out.display());
+
+    let mut t0: Option<u64> = None;
+    let mut count = 0usize;
+    while !stop.load(Ordering::Relaxed) {
+        let mut any_event = false;
+        if let Ok(events) = dev.fetch_events() {
+            for ev in events {
+                any_event = true;
+                let raw = RawEvent::from(&ev);
+                let base = *t0.get_or_insert(raw.time_us);
+                write_event(&mut w, &raw, base)?;
+                count += 1;
+                if raw.kind == EventType::SYNCHRONIZATION.0
+                    && raw.code == SynchronizationCode::SYN_REPORT.0
+                {
+                    w.flush()?;
+                }
+            }
+        }
+        if !any_event {
+            thread::sleep(Duration::from_millis(4));
+        }
+    }
+    w.flush()?;
+    Ok(count)
+}
+
+fn write_header(
+    w: &mut impl Write,
+    name: &str,
+    path: &str,
+    geometry: Option<&Geometry>,
+) -> Result<()> {
+    writeln!(w, "{HEADER}")?;
+    writeln!(w, "# device: {name}")?;
+    writeln!(w, "# path: {path}")?;
+    if let Some(g) = geometry {
+        for (key, a) in [("abs_x", g.x), ("abs_y", g.y)] {
+            writeln!(w, "# {key}: {} {} {}", a.min, a.max, a.resolution)?;
+        }
+        if let Some(a) = g.pressure {
+            writeln!(w, "# abs_pressure: {} {} {}", a.min, a.max, a.resolution)?;
+        }
+        writeln!(w, "# slots: {}", g.slots)?;
+    }
+    Ok(())
+}
+
+/// One event line, timed relative to `base_us`.
+fn write_event(w: &mut impl Write, ev: &RawEvent, base_us: u64) -> Result<()> {
+    writeln!(
+        w,
+        "{} {} {} {}",
+        ev.time_us.saturating_sub(base_us),
+        ev.kind,
+        ev.code,
+        ev.value
+    )?;
+    Ok(())
+}
+
+/// Parse a recording produced by [`record`].
+pub fn parse_recording(txt: &str) -> Result<Recording> {
+    let mut lines = txt.lines().enumerate();
+    match lines.next() {
+        Some((_, l)) if l.trim() == HEADER => {}
+        _ => return Err(anyhow!("not a touchctl recording (missing '{HEADER}')")),
+    }
+
+    let mut out = Vec::new();
+    let (mut abs_x, mut abs_y, mut abs_pressure, mut slots) = (None, None, None, None);
+    for (i, line) in lines {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some((key, val)) = comment.split_once(':') {
+                if key.trim() == "slots" {
+                    let n = val.trim().parse::<usize>();
+                    slots = Some(n.map_err(|_| anyhow!("line {}: invalid slots", i + 1))?);
+                    continue;
+                }
+                let slot = match key.trim() {
+                    "abs_x" => &mut abs_x,
+                    "abs_y" => &mut abs_y,
+                    "abs_pressure" => &mut abs_pressure,
+                    _ => continue,
+                };
+                *slot = Some(
+                    parse_abs_axis(val).ok_or_else(|| anyhow!("line {}: invalid {key}", i + 1))?,
+                );
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let f: Vec<&str> = line.split_whitespace().collect();
+        if f.len() != 4 {
+            return Err(anyhow!(
+                "line {}: expected 4 fields, got {}",
+                i + 1,
+                f.len()
+            ));
+        }
+        let bad = |what: &str| anyhow!("line {}: invalid {what}", i + 1);
+        out.push(RawEvent {
+            time_us: f[0].parse().map_err(|_| bad("time"))?,
+            kind: f[1].parse().map_err(|_| bad("type"))?,
+            code: f[2].parse().map_err(|_| bad("code"))?,
+            value: f[3].parse().map_err(|_| bad("value"))?,
+        });
+    }
+    let mut geometry = match (abs_x, abs_y) {
+        (Some(x), Some(y)) => Geometry {
+            x,
+            y,
+            ..Geometry::default()
+        },
+        _ => Geometry::default(),
+    };
+    if let Some(n) = slots {
+        geometry.slots = n.min(MAX_SLOTS);
+    }
+    geometry.pressure = abs_pressure;
+    Ok(Recording {
+        geometry,
+        events: out,
+    })
+}
+
+/// `<min> <max> <resolution>`
+fn parse_abs_axis(s: &str) -> Option<AbsAxis> {
+    let f: Vec<i32> = s
+        .split_whitespace()
+        .map(|v| v.parse().ok())
+        .collect::<Option<_>>()?;
+    match f[..] {
+        [min, max, resolution] => Some(AbsAxis {
+            min,
+            max,
+            resolution,
+        }),
+        _ => None,
+    }
+}
+
+/// Feed recorded events through `Tracker` and `GestureDetector`. Like the
+/// live pipeline, the event timestamps are the clock: a hold that comes due
+/// between two events is reported at its deadline. No device or uinput access
+/// is needed.
+pub fn replay(events: &[RawEvent], geometry: Geometry, th: Thresholds) -> Replay {
+    let th = th.for_surface(geometry.size_mm());
+    let mut tracker = Tracker::new();
+    geometry.apply_to(&mut tracker);
+    tracker.set_palm_filter(th.palm_filter());
+    tracker.set_zones(th.zone_filter());
+    tracker.set_y_scale(th.y_scale);
+    let mut detector = GestureDetector::new(th);
+    let mut prev_frame: Option<FrameSummary> = None;
+    let mut out = Replay::default();
+
+    for ev in events {
+        let ev_ms = (ev.time_us / 1000) as u128;
+        if let Some(due) = detector.hold_deadline_ms()
+            && due <= ev_ms
+            && let Some(gesture) = detector.tick(due)
+        {
+            out.hits.push(ReplayHit { t_ms: due, gesture });
+        }
+        if let Some(frame) = input::feed_tracker(&mut tracker, ev) {
+            for gesture in detector.update(&frame, prev_frame.as_ref()) {
+                out.hits.push(ReplayHit {
+                    t_ms: frame.timestamp_ms,
+                    gesture,
+                });
+            }
+            out.phases
+                .extend(detector.take_phases().into_iter().map(|event| ReplayPhase {
+                    t_ms: frame.timestamp_ms,
+                    event,
+                }));
+            prev_frame = Some(frame);
+        }
+    }
+    out
+}
+
+pub fn replay_file(path: &Path, th: Thresholds) -> Result<Replay> {
+    let txt =
+        fs::read_to_string(path).map_err(|e| anyhow!("failed to read {}: {e}", path.display()))?;
+    let rec = parse_recording(&txt).map_err(|e| anyhow!("{}: {e}", path.display()))?;
+    Ok(replay(&rec.events, rec.geometry, th))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recordings_round_trip() {
+        let geometry = Geometry {
+            x: AbsAxis {
+                min: 1266,
+                max: 5676,
+                resolution: 42,
+            },
+            y: AbsAxis {
+                min: 1096,
+                max: 4758,
+                resolution: 37,
+            },
+            pressure: Some(AbsAxis {
+                min: 0,
+                max: 255,
+                resolution: 0,
+            }),
+            slots: 5,
+        };
+        let ev = |time_us, kind, code, value| RawEvent {
+            time_us,
+            kind,
+            code,
+            value,
+        };
+        let events = [
+            ev(1_000_000, 3, 47, 0),
+            ev(1_000_000, 3, 57, 12),
+            ev(1_000_000, 3, 53, -4),
+            ev(1_012_345, 0, 0, 0),
+        ];
+
+        let mut out = Vec::new();
+        write_header(&mut out, "pad", "/dev/input/event5", Some(&geometry)).unwrap();
+        for e in &events {
+            write_event(&mut out, e, 1_000_000).unwrap();
+        }
+        let rec = parse_recording(&String::from_utf8(out).unwrap()).unwrap();
+
+        assert_eq!(rec.geometry, geometry);
+        let times: Vec<u64> = rec.events.iter().map(|e| e.time_us).collect();
+        assert_eq!(times, [0, 0, 0, 12_345]);
+        for (got, want) in rec.events.iter().zip(&events) {
+            assert_eq!(
+                (got.kind, got.code, got.value),
+                (want.kind, want.code, want.value)
+            );
+        }
+    }
+
+    #[test]
+    fn parses_geometry_headers() {
+        let rec = parse_recording(
+            "# touchctl-rec v1\n# device: pad\n# abs_x: 1266 5676 42\n# abs_y: 1096 4758 37\n0 0 0 0\n",
+        )
+        .unwrap();
+        assert_eq!(
+            rec.geometry.x,
+            AbsAxis {
+                min: 1266,
+                max: 5676,
+                resolution: 42
+            }
+        );
+        assert_eq!(rec.geometry.y.max, 4758);
+        assert_eq!(rec.events.len(), 1);
+        let (w, h) = rec.geometry.size_mm().unwrap();
+        assert!((w - 105.0).abs() < 1e-3 && (h - 3662.0 / 37.0).abs() < 1e-3);
+
+        assert_eq!(rec.geometry.slots, Geometry::default().slots);
+
+        let rec = parse_recording("# touchctl-rec v1\n# abs_x: 0 100 0\n").unwrap();
+        assert_eq!(rec.geometry, Geometry::default());
+        let rec = parse_recording("# touchctl-rec v1\n# slots: 0\n").unwrap();
+        assert_eq!(rec.geometry.slots, 0);
+        assert!(parse_recording("# touchctl-rec v1\n# abs_y: 0 x 0\n").is_err());
+    }
+}
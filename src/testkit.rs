@@ -0,0 +1,250 @@
+//! Scripted touch sessions for tests.
+//!
+//! A [`Script`] turns finger actions into the raw evdev stream a real
+//! touchpad would produce, then runs it through the replay path
+//! (`input::feed_tracker` -> `Tracker` -> `GestureDetector`).
+//!
+//! ```ignore
+//! let gestures = Script::new()
+//!     .down(0, 0.3, 0.5)
+//!     .down(1, 0.5, 0.5)
+//!     .sync()
+//!     .move_to(0, 0.3, 0.2)
+//!     .move_to(1, 0.5, 0.2)
+//!     .over(120)
+//!     .lift(0)
+//!     .lift(1)
+//!     .sync()
+//!     .run(th());
+//! ```
+
+use evdev::{AbsoluteAxisCode, EventType, SynchronizationCode};
+
+use crate::config::{DistanceUnit, Thresholds};
+use crate::gestures::{Gesture, PhaseEvent};
+use crate::input::{Geometry, RawEvent};
+use crate::replay::{self, Replay, ReplayHit};
+
+/// Raw units per normalized unit; matches the tracker's default 0..4096 box.
+const RAW_SCALE: f32 = 4096.0;
+/// Frame interval used when interpolating movement.
+pub const FRAME_MS: u64 = 10;
+
+#[derive(Debug, Clone, Copy)]
+enum Pending {
+    Down(usize, f32, f32),
+    Move(usize, f32, f32),
+    Lift(usize),
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Script {
+    t_ms: u64,
+    next_tracking_id: i32,
+    /// Last emitted position per finger (finger index == MT slot).
+    pos: Vec<Option<(f32, f32)>>,
+    pending: Vec<Pending>,
+    events: Vec<RawEvent>,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Put finger `f` down at normalized `(x, y)` in the next frame.
+    pub fn down(mut self, f: usize, x: f32, y: f32) -> Self {
+        self.pending.push(Pending::Down(f, x, y));
+        self
+    }
+
+    /// Move finger `f` to `(x, y)`; jumps on [`sync`](Self::sync), glides on [`over`](Self::over).
+    pub fn move_to(mut self, f: usize, x: f32, y: f32) -> Self {
+        self.pending.push(Pending::Move(f, x, y));
+        self
+    }
+
+    /// Lift finger `f` in the next frame.
+    pub fn lift(mut self, f: usize) -> Self {
+        self.pending.push(Pending::Lift(f));
+        self
+    }
+
+    /// Advance the clock without emitting anything.
+    pub fn wait(mut self, ms: u64) -> Self {
+        self.t_ms += ms;
+        self
+    }
+
+    /// Emit all pending actions as one frame at the current time.
+    pub fn sync(mut self) -> Self {
+        let pending = std::mem::take(&mut self.pending);
+        for p in pending {
+            self.apply(p);
+        }
+        self.syn();
+        self
+    }
+
+    /// Spread pending moves over `ms`, one frame every [`FRAME_MS`].
+    /// Downs land in the first frame, lifts happen after the last one.
+    pub fn over(mut self, ms: u64) -> Self {
+        let pending = std::mem::take(&mut self.pending);
+        let starts: Vec<(usize, (f32, f32))> = pending
+            .iter()
+            .filter_map(|p| match *p {
+                Pending::Move(f, ..) => self.pos.get(f).copied().flatten().map(|s| (f, s)),
+                _ => None,
+            })
+            .collect();
+
+        for p in &pending {
+            if let Pending::Down(..) = p {
+                self.apply(*p);
+            }
+        }
+
+        let n = (ms / FRAME_MS).max(1);
+        for i in 1..=n {
+            self.t_ms += ms / n;
+            let k = i as f32 / n as f32;
+            for p in &pending {
+                if let Pending::Move(f, x, y) = *p {
+                    let (sx, sy) = starts
+                        .iter()
+                        .find(|(sf, _)| *sf == f)
+                        .map(|(_, s)| *s)
+                        .unwrap_or((x, y));
+                    self.apply(Pending::Move(f, sx + (x - sx) * k, sy + (y - sy) * k));
+                }
+            }
+            self.syn();
+        }
+
+        let lifts: Vec<Pending> = pending
+            .into_iter()
+            .filter(|p| matches!(p, Pending::Lift(_)))
+            .collect();
+        if !lifts.is_empty() {
+            for p in lifts {
+                self.apply(p);
+            }
+            self.syn();
+        }
+        self
+    }
+
+    /// Replay the script and return everything it recognized.
+    pub fn replay(self, th: Thresholds) -> Replay {
+        assert!(self.pending.is_empty(), "script ends with unsynced actions");
+        replay::replay(&self.events, Geometry::default(), th)
+    }
+
+    /// Replay the script and return the gestures with their timestamps.
+    pub fn run_timed(self, th: Thresholds) -> Vec<ReplayHit> {
+        self.replay(th).hits
+    }
+
+    /// Replay the script and return the phases of continuous gestures.
+    pub fn run_phases(self, th: Thresholds) -> Vec<PhaseEvent> {
+        self.replay(th)
+            .phases
+            .into_iter()
+            .map(|p| p.event)
+            .collect()
+    }
+
+    /// Replay the script and return the gestures in order.
+    pub fn run(self, th: Thresholds) -> Vec<Gesture> {
+        self.run_timed(th).into_iter().map(|h| h.gesture).collect()
+    }
+
+    fn apply(&mut self, p: Pending) {
+        match p {
+            Pending::Down(f, x, y) => {
+                self.abs(AbsoluteAxisCode::ABS_MT_SLOT, f as i32);
+                let id = self.next_tracking_id;
+                self.next_tracking_id += 1;
+                self.abs(AbsoluteAxisCode::ABS_MT_TRACKING_ID, id);
+                self.abs(AbsoluteAxisCode::ABS_MT_POSITION_X, raw(x));
+                self.abs(AbsoluteAxisCode::ABS_MT_POSITION_Y, raw(y));
+                self.set_pos(f, Some((x, y)));
+            }
+            Pending::Move(f, x, y) => {
+                let Some((px, py)) = self.pos.get(f).copied().flatten() else {
+                    panic!("finger {f} moved while up");
+                };
+                self.abs(AbsoluteAxisCode::ABS_MT_SLOT, f as i32);
+                // like the kernel, only report axes that changed
+                if raw(x) != raw(px) {
+                    self.abs(AbsoluteAxisCode::ABS_MT_POSITION_X, raw(x));
+                }
+                if raw(y) != raw(py) {
+                    self.abs(AbsoluteAxisCode::ABS_MT_POSITION_Y, raw(y));
+                }
+                self.set_pos(f, Some((x, y)));
+            }
+            Pending::Lift(f) => {
+                self.abs(AbsoluteAxisCode::ABS_MT_SLOT, f as i32);
+                self.abs(AbsoluteAxisCode::ABS_MT_TRACKING_ID, -1);
+                self.set_pos(f, None);
+            }
+        }
+    }
+
+    fn set_pos(&mut self, f: usize, p: Option<(f32, f32)>) {
+        if self.pos.len() <= f {
+            self.pos.resize(f + 1, None);
+        }
+        self.pos[f] = p;
+    }
+
+    fn abs(&mut self, code: AbsoluteAxisCode, value: i32) {
+        self.push(EventType::ABSOLUTE.0, code.0, value);
+    }
+
+    fn syn(&mut self) {
+        self.push(
+            EventType::SYNCHRONIZATION.0,
+            SynchronizationCode::SYN_REPORT.0,
+            0,
+        );
+    }
+
+    fn push(&mut self, kind: u16, code: u16, value: i32) {
+        self.events.push(RawEvent {
+            time_us: self.t_ms * 1000,
+            kind,
+            code,
+            value,
+        });
+    }
+}
+
+fn raw(n: f32) -> i32 {
+    (n * RAW_SCALE).round() as i32
+}
+
+/// Thresholds used by the golden tests; independent of profiles/default.toml.
+pub fn th() -> Thresholds {
+    Thresholds {
+        tap_ms: 200,
+        hold_ms: 350,
+        move_tol: 0.025,
+        swipe_min_dist: 0.08,
+        swipe_max_ms: 500,
+        pinch_sensitivity: 1.0,
+        pinch_step: 0.05,
+        rotate_step_deg: 15.0,
+        smooth_ema: 0.3,
+        kinetic_friction: 4.0,
+        kinetic_min_velocity: 0.3,
+        distance_unit: DistanceUnit::Norm,
+        palm_rejection: true,
+        palm_max_size: 0.0,
+        palm_max_pressure: 0.0,
+        edge_zones: vec![],
+        thumb_zone: None,
+        y_scale: 1.0,
+    }
+}
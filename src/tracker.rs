@@ -1,6 +1,65 @@
 //! Per-slot touch tracking and frame snapshots.
+//!
+//! Slotted (protocol B) devices address slots directly. Protocol A devices
+//! report an anonymous list of contacts each frame (`SYN_MT_REPORT` after
+//! each); the tracker assigns those to slots itself, by tracking id when the
+//! device sends one and by nearest previous position otherwise.
 
-use std::time::Instant;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// Upper bound on slots, whatever a device claims.
+pub const MAX_SLOTS: usize = 64;
+/// Slots used until the device's ABS_MT_SLOT range is known.
+pub const DEFAULT_SLOTS: usize = 10;
+/// ABS_MT_TOOL_TYPE value the kernel uses for palms.
+const MT_TOOL_PALM: i32 = 2;
+
+/// Contact shape and kind as the device reports them, in raw units.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TouchAttrs {
+    pub touch_major: i32,
+    pub touch_minor: i32,
+    pub pressure: i32,
+    pub tool_type: i32,
+}
+
+/// Which contacts are palms and left out of frames. Limits of 0 are off.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PalmFilter {
+    pub enabled: bool,
+    /// Largest normalized touch axis, major or minor, still counted as a
+    /// finger.
+    pub max_size: f32,
+    /// Largest pressure, as a fraction of the pressure range, still counted.
+    pub max_pressure: f32,
+}
+
+/// A rectangle of the pad in normalized units, `x0,y0` top left.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Zone {
+    pub x0: f32,
+    pub y0: f32,
+    pub x1: f32,
+    pub y1: f32,
+}
+
+impl Zone {
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        (self.x0..=self.x1).contains(&x) && (self.y0..=self.y1).contains(&y)
+    }
+}
+
+/// Areas of the pad where touches don't count as fingers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ZoneFilter {
+    /// Touches that start here are ignored until they leave every edge zone.
+    pub edges: Vec<Zone>,
+    /// Touches resting here are ignored until they travel `thumb_move`.
+    pub thumb: Option<Zone>,
+    pub thumb_move: f32,
+}
 
 #[derive(Debug, Clone, Default)]
 pub struct SlotState {
@@ -10,12 +69,19 @@ pub struct SlotState {
     pub t_first_ms: u128,
     pub t_last_ms: u128,
     pub moved_norm: f32,
+    pub attrs: TouchAttrs,
     // internal
     last_x_norm: f32,
     last_y_norm: f32,
     seen_x: bool, // ⬅️ NEW: baseline flags
     seen_y: bool, // ⬅️ NEW
     pub(crate) active: bool,
+    // once a palm, always a palm until the touch lifts
+    palm: bool,
+    // not yet seen in a frame
+    fresh: bool,
+    // started in an edge zone and hasn't left it yet
+    in_edge: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -25,6 +91,20 @@ pub struct SlotSnapshot {
     pub y_norm: f32,
     pub moved_norm: f32,
     pub age_ms: u64,
+    /// Touch ellipse axes, normalized like the distance thresholds.
+    pub touch_major: f32,
+    pub touch_minor: f32,
+    /// Fraction of the device's pressure range; 0 without pressure.
+    pub pressure: f32,
+    pub tool_type: i32,
+}
+
+/// One MT slot as read back from the device, in raw units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotValues {
+    pub tracking_id: i32,
+    pub x: i32,
+    pub y: i32,
 }
 
 #[derive(Debug, Clone)]
@@ -33,20 +113,49 @@ pub struct FrameSummary {
     pub active_count: usize,
     pub centroid: (f32, f32),
     pub span: f32,
+    /// Mean turn of the touches around the centroid since the previous frame,
+    /// in degrees, clockwise positive; 0 when the set of touches changed.
+    pub rotation: f32,
     pub slots: Vec<SlotSnapshot>,
 }
 
+/// A protocol A contact as it is being reported.
+#[derive(Debug, Clone, Copy, Default)]
+struct Contact {
+    tracking_id: Option<i32>,
+    x: Option<i32>,
+    y: Option<i32>,
+    attrs: TouchAttrs,
+}
+
 #[derive(Debug)]
 pub struct Tracker {
     slots: Vec<SlotState>,
     cur_slot: i32,
+    // the current slot is out of range; its events are dropped until the
+    // next ABS_MT_SLOT
+    bad_slot: bool,
+    bad_slot_warned: bool,
+    // protocol A: contacts of the frame being reported
+    protocol_a: bool,
+    contact: Contact,
+    contacts: Vec<Contact>,
+    next_tracking_id: i32,
     // normalization
     x_min: i32,
     x_max: i32,
     y_min: i32,
     y_max: i32,
-    // time
-    start_instant: Instant,
+    pressure_range: Option<(i32, i32)>,
+    palm_filter: PalmFilter,
+    zones: ZoneFilter,
+    // length of a normalized unit of y in units of x
+    y_scale: f32,
+    // time of the event being fed, from the kernel (or a recording)
+    clock_ms: u128,
+    // (tracking id, direction from the centroid in degrees, distance) of each
+    // counted touch in the last frame
+    angles: Vec<(i32, f32, f32)>,
     pub active_count: usize,
     pub centroid: (f32, f32),
     pub span: f32,
@@ -61,19 +170,44 @@ impl Default for Tracker {
 impl Tracker {
     pub fn new() -> Self {
         Self {
-            slots: vec![SlotState::default(); 10],
+            slots: vec![SlotState::default(); DEFAULT_SLOTS],
             cur_slot: 0,
+            bad_slot: false,
+            bad_slot_warned: false,
+            protocol_a: false,
+            contact: Contact::default(),
+            contacts: Vec::new(),
+            next_tracking_id: 0,
             x_min: 0,
             x_max: 4096,
             y_min: 0,
             y_max: 4096,
-            start_instant: Instant::now(),
+            pressure_range: None,
+            palm_filter: PalmFilter::default(),
+            zones: ZoneFilter::default(),
+            y_scale: 1.0,
+            clock_ms: 0,
+            angles: Vec::new(),
             active_count: 0,
             centroid: (0.0, 0.0),
             span: 0.0,
         }
     }
 
+    /// Size the slot table from the device's ABS_MT_SLOT range. `0` means the
+    /// device has no slots (protocol A) and the tracker assigns them.
+    pub fn set_slot_count(&mut self, count: usize) {
+        self.protocol_a = count == 0;
+        let count = if self.protocol_a {
+            DEFAULT_SLOTS
+        } else {
+            count
+        };
+        self.slots = vec![SlotState::default(); count.min(MAX_SLOTS)];
+        self.cur_slot = 0;
+        self.bad_slot = false;
+    }
+
     pub fn set_norm_ranges(&mut self, x_min: i32, x_max: i32, y_min: i32, y_max: i32) {
         self.x_min = x_min;
         self.x_max = x_max.max(x_min + 1);
@@ -81,15 +215,66 @@ impl Tracker {
         self.y_max = y_max.max(y_min + 1);
     }
 
+    pub fn set_pressure_range(&mut self, min: i32, max: i32) {
+        self.pressure_range = Some((min, max.max(min + 1)));
+    }
+
+    pub fn set_palm_filter(&mut self, filter: PalmFilter) {
+        self.palm_filter = filter;
+    }
+
+    pub fn set_zones(&mut self, zones: ZoneFilter) {
+        self.zones = zones;
+    }
+
+    /// Measure distances (`moved_norm`, span, rotation) in units of x, with
+    /// y scaled by `y_scale`; see [`Thresholds::y_scale`].
+    ///
+    /// [`Thresholds::y_scale`]: crate::config::Thresholds::y_scale
+    pub fn set_y_scale(&mut self, y_scale: f32) {
+        self.y_scale = y_scale;
+    }
+
+    /// Set the time of the events that follow. All ages and frame times are
+    /// derived from this, never from when the events happen to be processed.
+    pub fn set_clock_ms(&mut self, ms: u128) {
+        self.clock_ms = ms;
+    }
+
     fn now_ms(&self) -> u128 {
-        self.start_instant.elapsed().as_millis()
+        self.clock_ms
     }
 
     pub fn on_slot(&mut self, slot: i32) {
-        self.cur_slot = slot.clamp(0, (self.slots.len() as i32) - 1);
+        self.bad_slot = slot < 0 || slot as usize >= MAX_SLOTS;
+        if self.bad_slot {
+            if !self.bad_slot_warned {
+                self.bad_slot_warned = true;
+                warn!("ABS_MT_SLOT {slot} is out of range; ignoring its touches");
+            }
+            return;
+        }
+        // the device reported more slots than its range claimed
+        if slot as usize >= self.slots.len() {
+            self.slots.resize(slot as usize + 1, SlotState::default());
+        }
+        self.cur_slot = slot;
     }
 
     pub fn on_tracking_id(&mut self, tracking_id: i32) {
+        if self.protocol_a {
+            if tracking_id >= 0 {
+                self.contact.tracking_id = Some(tracking_id);
+            }
+            return;
+        }
+        if self.bad_slot {
+            return;
+        }
+        self.set_tracking_id(tracking_id);
+    }
+
+    fn set_tracking_id(&mut self, tracking_id: i32) {
         let now = self.now_ms();
         let s = &mut self.slots[self.cur_slot as usize];
         if tracking_id < 0 {
@@ -99,6 +284,7 @@ impl Tracker {
             s.t_last_ms = now;
         } else {
             // new touch → reset movement and clear baselines
+            // like positions, attributes the kernel doesn't resend carry over
             *s = SlotState {
                 tracking_id,
                 x_norm: s.x_norm,
@@ -106,16 +292,31 @@ impl Tracker {
                 t_first_ms: now,
                 t_last_ms: now,
                 moved_norm: 0.0,
+                attrs: s.attrs,
                 last_x_norm: s.x_norm,
                 last_y_norm: s.y_norm,
                 seen_x: false,
                 seen_y: false,
                 active: true,
+                palm: false,
+                fresh: true,
+                in_edge: false,
             };
         }
     }
 
     pub fn on_pos_x(&mut self, raw: i32) {
+        if self.protocol_a {
+            self.contact.x = Some(raw);
+            return;
+        }
+        if self.bad_slot {
+            return;
+        }
+        self.set_pos_x(raw);
+    }
+
+    fn set_pos_x(&mut self, raw: i32) {
         let now = self.now_ms();
         let x_min = self.x_min;
         let x_max = self.x_max;
@@ -127,44 +328,274 @@ impl Tracker {
             s.moved_norm += dx.abs();
         } else {
             // establish baseline, don't count movement yet
-            s.last_x_norm = nx;
             s.seen_x = true;
         }
+        s.last_x_norm = nx;
         s.x_norm = nx;
         s.t_last_ms = now;
     }
 
     pub fn on_pos_y(&mut self, raw: i32) {
+        if self.protocol_a {
+            self.contact.y = Some(raw);
+            return;
+        }
+        if self.bad_slot {
+            return;
+        }
+        self.set_pos_y(raw);
+    }
+
+    fn set_pos_y(&mut self, raw: i32) {
         let now = self.now_ms();
         let y_min = self.y_min;
         let y_max = self.y_max;
         let ny = ((raw - y_min) as f32 / (y_max - y_min) as f32).clamp(0.0, 1.0);
 
+        let y_scale = self.y_scale;
         let s = &mut self.slots[self.cur_slot as usize];
         if s.seen_x && s.seen_y {
             let dy = ny - s.last_y_norm;
-            s.moved_norm += dy.abs();
+            s.moved_norm += dy.abs() * y_scale;
         } else {
-            s.last_y_norm = ny;
             s.seen_y = true;
         }
+        s.last_y_norm = ny;
         s.y_norm = ny;
         s.t_last_ms = now;
     }
 
+    pub fn on_touch_major(&mut self, v: i32) {
+        if let Some(a) = self.attrs_mut() {
+            a.touch_major = v;
+        }
+    }
+
+    pub fn on_touch_minor(&mut self, v: i32) {
+        if let Some(a) = self.attrs_mut() {
+            a.touch_minor = v;
+        }
+    }
+
+    pub fn on_pressure(&mut self, v: i32) {
+        if let Some(a) = self.attrs_mut() {
+            a.pressure = v;
+        }
+    }
+
+    pub fn on_tool_type(&mut self, v: i32) {
+        if let Some(a) = self.attrs_mut() {
+            a.tool_type = v;
+        }
+    }
+
+    fn attrs_mut(&mut self) -> Option<&mut TouchAttrs> {
+        if self.protocol_a {
+            Some(&mut self.contact.attrs)
+        } else if self.bad_slot {
+            None
+        } else {
+            Some(&mut self.slots[self.cur_slot as usize].attrs)
+        }
+    }
+
+    /// Protocol A: the contact being reported is complete. An empty report
+    /// means no fingers are down.
+    pub fn on_mt_report(&mut self) {
+        let c = std::mem::take(&mut self.contact);
+        if self.protocol_a && c.x.is_some() && c.y.is_some() {
+            self.contacts.push(c);
+        }
+    }
+
+    /// Protocol A: map this frame's contacts onto slots. A contact keeps the
+    /// slot with its tracking id, or else the nearest active slot; the rest
+    /// get free slots, and slots left without a contact are released.
+    fn assign_contacts(&mut self) {
+        self.on_mt_report();
+        let contacts = std::mem::take(&mut self.contacts);
+        let mut slot_of: Vec<Option<usize>> = vec![None; contacts.len()];
+        let mut taken = vec![false; self.slots.len()];
+
+        for (ci, c) in contacts.iter().enumerate() {
+            let Some(id) = c.tracking_id else { continue };
+            if let Some(si) = self
+                .slots
+                .iter()
+                .position(|s| s.active && s.tracking_id == id)
+            {
+                slot_of[ci] = Some(si);
+                taken[si] = true;
+            }
+        }
+
+        // anonymous contacts: closest pairs first
+        let mut pairs = vec![];
+        for (ci, c) in contacts.iter().enumerate() {
+            if c.tracking_id.is_some() {
+                continue;
+            }
+            let (x, y) = self.normalize(c.x.unwrap_or(0), c.y.unwrap_or(0));
+            for (si, s) in self.slots.iter().enumerate() {
+                if s.active && !taken[si] {
+                    let d = (s.x_norm - x).powi(2) + (s.y_norm - y).powi(2);
+                    pairs.push((d, ci, si));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for (_, ci, si) in pairs {
+            if slot_of[ci].is_none() && !taken[si] {
+                slot_of[ci] = Some(si);
+                taken[si] = true;
+            }
+        }
+
+        for (si, taken) in taken.into_iter().enumerate() {
+            if self.slots[si].active && !taken {
+                self.cur_slot = si as i32;
+                self.set_tracking_id(-1);
+            }
+        }
+
+        for (ci, c) in contacts.iter().enumerate() {
+            let si = match slot_of[ci] {
+                Some(si) => si,
+                None => {
+                    let free = match self.slots.iter().position(|s| !s.active) {
+                        Some(si) => si,
+                        None if self.slots.len() < MAX_SLOTS => {
+                            self.slots.push(SlotState::default());
+                            self.slots.len() - 1
+                        }
+                        None => continue,
+                    };
+                    let id = c.tracking_id.unwrap_or_else(|| {
+                        self.next_tracking_id = (self.next_tracking_id + 1) & 0xffff;
+                        self.next_tracking_id
+                    });
+                    self.cur_slot = free as i32;
+                    self.set_tracking_id(id);
+                    free
+                }
+            };
+            self.cur_slot = si as i32;
+            self.set_pos_x(c.x.unwrap_or(0));
+            self.set_pos_y(c.y.unwrap_or(0));
+            self.slots[si].attrs = c.attrs;
+        }
+    }
+
+    /// Touch size in the same normalized units as the distance thresholds
+    /// (the mean side of the pad).
+    fn size_norm(&self, raw: i32) -> f32 {
+        raw as f32 / ((self.x_max - self.x_min + self.y_max - self.y_min) as f32 / 2.0)
+    }
+
+    fn pressure_norm(&self, raw: i32) -> f32 {
+        match self.pressure_range {
+            Some((min, max)) => ((raw - min) as f32 / (max - min) as f32).clamp(0.0, 1.0),
+            None => 0.0,
+        }
+    }
+
+    fn is_resting_thumb(&self, s: &SlotState) -> bool {
+        self.zones.thumb.is_some_and(|z| {
+            z.contains(s.x_norm, s.y_norm) && s.moved_norm <= self.zones.thumb_move
+        })
+    }
+
+    fn looks_like_palm(&self, s: &SlotSnapshot) -> bool {
+        let f = &self.palm_filter;
+        f.enabled
+            && (s.tool_type == MT_TOOL_PALM
+                || (f.max_size > 0.0 && s.touch_major.max(s.touch_minor) > f.max_size)
+                || (f.max_pressure > 0.0 && s.pressure > f.max_pressure))
+    }
+
+    /// `s` as it goes into a frame at `now`.
+    fn snapshot(&self, s: &SlotState, now: u128) -> SlotSnapshot {
+        SlotSnapshot {
+            tracking_id: s.tracking_id,
+            x_norm: s.x_norm,
+            y_norm: s.y_norm,
+            moved_norm: s.moved_norm,
+            age_ms: now.saturating_sub(s.t_first_ms) as u64,
+            touch_major: self.size_norm(s.attrs.touch_major),
+            touch_minor: self.size_norm(s.attrs.touch_minor),
+            pressure: self.pressure_norm(s.attrs.pressure),
+            tool_type: s.attrs.tool_type,
+        }
+    }
+
+    fn normalize(&self, x: i32, y: i32) -> (f32, f32) {
+        (
+            ((x - self.x_min) as f32 / (self.x_max - self.x_min) as f32).clamp(0.0, 1.0),
+            ((y - self.y_min) as f32 / (self.y_max - self.y_min) as f32).clamp(0.0, 1.0),
+        )
+    }
+
+    /// Replace the slot state with what the device reports after events
+    /// were dropped. Touches that kept their tracking id keep their age and
+    /// movement; new ids start fresh and vanished ones are released.
+    pub fn resync(&mut self, cur_slot: i32, slots: &[SlotValues]) {
+        for (i, v) in slots.iter().enumerate().take(self.slots.len()) {
+            self.on_slot(i as i32);
+            let s = &self.slots[i];
+            if v.tracking_id < 0 {
+                if s.active {
+                    self.set_tracking_id(-1);
+                }
+                continue;
+            }
+            if !s.active || s.tracking_id != v.tracking_id {
+                self.set_tracking_id(v.tracking_id);
+            }
+            self.set_pos_x(v.x);
+            self.set_pos_y(v.y);
+        }
+        self.on_slot(cur_slot);
+        // positions jumped; don't read that as rotation
+        self.angles.clear();
+    }
+
     pub fn on_syn_report(&mut self) -> FrameSummary {
-        // active slots
-        let act: Vec<&SlotState> = self
+        if self.protocol_a {
+            self.assign_contacts();
+        }
+
+        let now = self.now_ms();
+        for i in 0..self.slots.len() {
+            let s = &self.slots[i];
+            if s.active && self.looks_like_palm(&self.snapshot(s, now)) {
+                self.slots[i].palm = true;
+            }
+        }
+        let zones = &self.zones;
+        for s in self.slots.iter_mut().filter(|s| s.active) {
+            let in_edge = zones.edges.iter().any(|z| z.contains(s.x_norm, s.y_norm));
+            if s.fresh {
+                s.in_edge = in_edge;
+                s.fresh = false;
+            } else if !in_edge {
+                s.in_edge = false;
+            }
+        }
+
+        // active slots; palms, edge touches and resting thumbs don't count
+        let slots: Vec<SlotSnapshot> = self
             .slots
             .iter()
-            .filter(|s| s.active && s.tracking_id >= 0)
+            .filter(|s| s.active && s.tracking_id >= 0 && !s.palm && !s.in_edge)
+            .filter(|s| !self.is_resting_thumb(s))
+            .map(|s| self.snapshot(s, now))
             .collect();
-        self.active_count = act.len();
+        self.active_count = slots.len();
 
         // centroid
         if self.active_count > 0 {
-            let sumx: f32 = act.iter().map(|s| s.x_norm).sum();
-            let sumy: f32 = act.iter().map(|s| s.y_norm).sum();
+            let sumx: f32 = slots.iter().map(|s| s.x_norm).sum();
+            let sumy: f32 = slots.iter().map(|s| s.y_norm).sum();
             self.centroid = (
                 sumx / self.active_count as f32,
                 sumy / self.active_count as f32,
@@ -176,34 +607,392 @@ impl Tracker {
         // span = avg distance from centroid
         if self.active_count > 0 {
             let mut acc = 0.0f32;
-            for s in &act {
+            for s in &slots {
                 let dx = s.x_norm - self.centroid.0;
-                let dy = s.y_norm - self.centroid.1;
-                acc += (dx * dx + dy * dy).sqrt();
+                let dy = (s.y_norm - self.centroid.1) * self.y_scale;
+                acc += dx.hypot(dy);
             }
             self.span = acc / self.active_count as f32;
         } else {
             self.span = 0.0;
         }
 
-        let now = self.now_ms();
-        let slots = act
-            .into_iter()
-            .map(|s| SlotSnapshot {
-                tracking_id: s.tracking_id,
-                x_norm: s.x_norm,
-                y_norm: s.y_norm,
-                moved_norm: s.moved_norm,
-                age_ms: (now - s.t_first_ms) as u64,
+        // rotation, weighted by distance from the centroid so a touch near
+        // the middle doesn't add noise
+        let angles: Vec<(i32, f32, f32)> = slots
+            .iter()
+            .map(|s| {
+                let dx = s.x_norm - self.centroid.0;
+                let dy = (s.y_norm - self.centroid.1) * self.y_scale;
+                (s.tracking_id, dy.atan2(dx).to_degrees(), dx.hypot(dy))
             })
             .collect();
+        let mut turn = 0.0f32;
+        let mut weight = 0.0f32;
+        if angles.len() >= 2 && angles.len() == self.angles.len() {
+            for &(id, a, r) in &angles {
+                let Some(&(_, prev, _)) = self.angles.iter().find(|(p, ..)| *p == id) else {
+                    turn = 0.0;
+                    weight = 0.0;
+                    break;
+                };
+                turn += wrap_deg(a - prev) * r;
+                weight += r;
+            }
+        }
+        let rotation = if weight > 0.0 { turn / weight } else { 0.0 };
+        self.angles = angles;
 
         FrameSummary {
             timestamp_ms: now,
             active_count: self.active_count,
             centroid: self.centroid,
             span: self.span,
+            rotation,
             slots,
         }
     }
 }
+
+/// An angle difference in degrees, wrapped into [-180, 180).
+fn wrap_deg(d: f32) -> f32 {
+    (d + 540.0).rem_euclid(360.0) - 180.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(t: &mut Tracker, slot: i32, id: i32, x: i32, y: i32) {
+        t.on_slot(slot);
+        t.on_tracking_id(id);
+        t.on_pos_x(x);
+        t.on_pos_y(y);
+    }
+
+    #[test]
+    fn centroid_and_span_of_two_fingers() {
+        let mut t = Tracker::new();
+        touch(&mut t, 0, 1, 1024, 2048);
+        touch(&mut t, 1, 2, 3072, 2048);
+        let f = t.on_syn_report();
+        assert_eq!(f.active_count, 2);
+        assert!((f.centroid.0 - 0.5).abs() < 1e-6);
+        assert!((f.centroid.1 - 0.5).abs() < 1e-6);
+        assert!((f.span - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rotation_is_the_mean_turn_around_the_centroid() {
+        let mut t = Tracker::new();
+        touch(&mut t, 0, 1, 1024, 2048);
+        touch(&mut t, 1, 2, 3072, 2048);
+        assert_eq!(t.on_syn_report().rotation, 0.0);
+
+        // a quarter turn clockwise (y grows downwards)
+        touch(&mut t, 0, 1, 2048, 1024);
+        touch(&mut t, 1, 2, 2048, 3072);
+        let f = t.on_syn_report();
+        assert!((f.rotation - 90.0).abs() < 1e-3, "{}", f.rotation);
+
+        // a new touch isn't a turn
+        touch(&mut t, 2, 3, 3000, 3000);
+        assert_eq!(t.on_syn_report().rotation, 0.0);
+    }
+
+    #[test]
+    fn release_drops_slot_from_frame() {
+        let mut t = Tracker::new();
+        touch(&mut t, 0, 1, 1000, 1000);
+        touch(&mut t, 1, 2, 2000, 2000);
+        t.on_syn_report();
+        t.on_slot(0);
+        t.on_tracking_id(-1);
+        let f = t.on_syn_report();
+        assert_eq!(f.active_count, 1);
+        assert_eq!(f.slots[0].tracking_id, 2);
+    }
+
+    #[test]
+    fn empty_frame_centres_centroid() {
+        let mut t = Tracker::new();
+        let f = t.on_syn_report();
+        assert_eq!(f.active_count, 0);
+        assert_eq!(f.centroid, (0.5, 0.5));
+        assert_eq!(f.span, 0.0);
+    }
+
+    #[test]
+    fn first_position_is_baseline_not_movement() {
+        let mut t = Tracker::new();
+        touch(&mut t, 0, 1, 4000, 4000);
+        let f = t.on_syn_report();
+        assert_eq!(f.slots[0].moved_norm, 0.0);
+    }
+
+    #[test]
+    fn norm_ranges_apply_and_clamp() {
+        let mut t = Tracker::new();
+        t.set_norm_ranges(100, 1100, -500, 500);
+        touch(&mut t, 0, 1, 600, 0);
+        touch(&mut t, 1, 2, 5000, -9000);
+        let f = t.on_syn_report();
+        assert!((f.slots[0].x_norm - 0.5).abs() < 1e-6);
+        assert!((f.slots[0].y_norm - 0.5).abs() < 1e-6);
+        assert_eq!((f.slots[1].x_norm, f.slots[1].y_norm), (1.0, 0.0));
+    }
+
+    #[test]
+    fn resync_keeps_known_touches_and_drops_ghosts() {
+        let mut t = Tracker::new();
+        t.set_clock_ms(1000);
+        touch(&mut t, 0, 1, 1000, 1000);
+        touch(&mut t, 1, 2, 2000, 2000);
+        t.on_syn_report();
+        // slot 1 lifted while events were dropped, slot 2 went down
+        t.set_clock_ms(1100);
+        let gone = SlotValues {
+            tracking_id: -1,
+            x: 0,
+            y: 0,
+        };
+        t.resync(
+            2,
+            &[
+                SlotValues {
+                    tracking_id: 1,
+                    x: 1100,
+                    y: 1000,
+                },
+                gone,
+                SlotValues {
+                    tracking_id: 3,
+                    x: 3000,
+                    y: 3000,
+                },
+            ],
+        );
+        let f = t.on_syn_report();
+        let ids: Vec<i32> = f.slots.iter().map(|s| s.tracking_id).collect();
+        assert_eq!(ids, [1, 3]);
+        assert_eq!(f.slots[0].age_ms, 100);
+        assert!(f.slots[0].moved_norm > 0.0);
+        assert_eq!(f.slots[1].age_ms, 0);
+    }
+
+    #[test]
+    fn high_slots_are_not_merged() {
+        let mut t = Tracker::new();
+        t.set_slot_count(16);
+        touch(&mut t, 9, 1, 1000, 1000);
+        touch(&mut t, 12, 2, 2000, 2000);
+        touch(&mut t, 15, 3, 3000, 3000);
+        assert_eq!(t.on_syn_report().active_count, 3);
+    }
+
+    #[test]
+    fn out_of_range_slots_leave_the_current_one_alone() {
+        let mut t = Tracker::new();
+        t.set_slot_count(4);
+        touch(&mut t, 0, 1, 1024, 1024);
+        for bad in [MAX_SLOTS as i32, -1] {
+            touch(&mut t, bad, 7, 3072, 3072);
+            t.on_pressure(255);
+            t.on_tool_type(MT_TOOL_PALM);
+        }
+        let f = t.on_syn_report();
+        assert_eq!(f.active_count, 1);
+        assert_eq!(f.slots[0].tracking_id, 1);
+        assert_eq!((f.slots[0].x_norm, f.slots[0].y_norm), (0.25, 0.25));
+
+        // a valid slot picks up again
+        touch(&mut t, 1, 2, 3072, 3072);
+        assert_eq!(t.on_syn_report().active_count, 2);
+    }
+
+    fn contact(t: &mut Tracker, x: i32, y: i32) {
+        t.on_pos_x(x);
+        t.on_pos_y(y);
+        t.on_mt_report();
+    }
+
+    #[test]
+    fn protocol_a_contacts_follow_nearest_slot() {
+        let mut t = Tracker::new();
+        t.set_slot_count(0);
+        contact(&mut t, 1000, 1000);
+        contact(&mut t, 3000, 3000);
+        let f = t.on_syn_report();
+        let (left, right) = (f.slots[0].tracking_id, f.slots[1].tracking_id);
+        assert_eq!(f.active_count, 2);
+        assert_ne!(left, right);
+
+        // reported in the opposite order, both moved a little
+        contact(&mut t, 3100, 3000);
+        contact(&mut t, 1100, 1000);
+        let f = t.on_syn_report();
+        assert_eq!(f.slots[0].tracking_id, left);
+        assert!(f.slots[0].x_norm < 0.3 && f.slots[0].moved_norm > 0.0);
+        assert_eq!(f.slots[1].tracking_id, right);
+
+        // left finger lifts; an empty report ends the frame
+        contact(&mut t, 3200, 3000);
+        let f = t.on_syn_report();
+        assert_eq!(f.active_count, 1);
+        assert_eq!(f.slots[0].tracking_id, right);
+        t.on_mt_report();
+        assert_eq!(t.on_syn_report().active_count, 0);
+    }
+
+    fn palm_filter() -> PalmFilter {
+        PalmFilter {
+            enabled: true,
+            max_size: 0.2,
+            max_pressure: 0.0,
+        }
+    }
+
+    #[test]
+    fn palms_are_left_out_of_the_frame() {
+        let mut t = Tracker::new();
+        t.set_palm_filter(palm_filter());
+        touch(&mut t, 0, 1, 1000, 1000);
+        touch(&mut t, 1, 2, 2000, 1000);
+        touch(&mut t, 2, 3, 3500, 3800);
+        t.on_touch_major(1200); // ~0.29 of the pad
+        touch(&mut t, 3, 4, 500, 3800);
+        t.on_tool_type(MT_TOOL_PALM);
+        let f = t.on_syn_report();
+        assert_eq!(f.active_count, 2);
+        assert!((f.centroid.0 - 1500.0 / 4096.0).abs() < 1e-6);
+        assert!(f.slots.iter().all(|s| s.tracking_id <= 2));
+
+        // shrinking back to finger size doesn't bring the palm back
+        t.on_slot(2);
+        t.on_touch_major(100);
+        assert_eq!(t.on_syn_report().active_count, 2);
+
+        t.set_palm_filter(PalmFilter::default());
+        touch(&mut t, 4, 5, 500, 500);
+        t.on_touch_major(2000);
+        t.on_touch_minor(1024);
+        let f = t.on_syn_report();
+        assert_eq!(f.active_count, 3);
+        let big = f.slots.iter().find(|s| s.tracking_id == 5).unwrap();
+        assert!((big.touch_major - 2000.0 / 4096.0).abs() < 1e-6);
+        assert!((big.touch_minor - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn either_touch_axis_can_exceed_the_palm_size() {
+        let mut t = Tracker::new();
+        t.set_palm_filter(palm_filter());
+        touch(&mut t, 0, 1, 1000, 1000);
+        touch(&mut t, 1, 2, 3000, 1000);
+        t.on_touch_minor(1200);
+        let f = t.on_syn_report();
+        assert_eq!(f.active_count, 1);
+        assert_eq!(f.slots[0].tracking_id, 1);
+    }
+
+    #[test]
+    fn pressure_limit_uses_the_device_range() {
+        let mut t = Tracker::new();
+        t.set_pressure_range(0, 255);
+        t.set_palm_filter(PalmFilter {
+            max_size: 0.0,
+            max_pressure: 0.5,
+            ..palm_filter()
+        });
+        touch(&mut t, 0, 1, 1000, 1000);
+        t.on_pressure(100);
+        touch(&mut t, 1, 2, 2000, 1000);
+        t.on_pressure(200);
+        let f = t.on_syn_report();
+        assert_eq!(f.active_count, 1);
+        assert_eq!(f.slots[0].tracking_id, 1);
+        assert!((f.slots[0].pressure - 100.0 / 255.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn moved_norm_is_path_length() {
+        // out 0.1, then three reports of the same spot, then back: a still
+        // finger must not keep adding its distance from the first position
+        let mut t = Tracker::new();
+        touch(&mut t, 0, 1, 0, 0);
+        for x in [410, 410, 410, 0] {
+            t.on_pos_x(x);
+            t.on_syn_report();
+        }
+        let f = t.on_syn_report();
+        assert!((f.slots[0].moved_norm - 820.0 / 4096.0).abs() < 1e-6);
+    }
+
+    fn bottom(y0: f32) -> Zone {
+        Zone {
+            x0: 0.0,
+            y0,
+            x1: 1.0,
+            y1: 1.0,
+        }
+    }
+
+    #[test]
+    fn edge_touches_count_once_they_leave_the_edge() {
+        let mut t = Tracker::new();
+        t.set_zones(ZoneFilter {
+            edges: vec![bottom(0.85)],
+            ..ZoneFilter::default()
+        });
+        touch(&mut t, 0, 1, 2048, 3900);
+        assert_eq!(t.on_syn_report().active_count, 0);
+        t.on_pos_y(2048);
+        assert_eq!(t.on_syn_report().active_count, 1);
+        // coming back doesn't make it an edge touch again
+        t.on_pos_y(3900);
+        assert_eq!(t.on_syn_report().active_count, 1);
+        // a touch that starts in the middle and slides onto the edge counts
+        touch(&mut t, 1, 2, 1000, 2048);
+        t.on_syn_report();
+        t.on_pos_y(4000);
+        assert_eq!(t.on_syn_report().active_count, 2);
+    }
+
+    #[test]
+    fn thumb_counts_once_it_moves() {
+        let mut t = Tracker::new();
+        t.set_zones(ZoneFilter {
+            thumb: Some(bottom(0.75)),
+            thumb_move: 0.05,
+            ..ZoneFilter::default()
+        });
+        touch(&mut t, 0, 1, 2048, 3500);
+        touch(&mut t, 1, 2, 2048, 1000);
+        assert_eq!(t.on_syn_report().active_count, 1);
+        t.on_slot(0);
+        t.on_pos_x(2048 + 410);
+        assert_eq!(t.on_syn_report().active_count, 2);
+    }
+
+    #[test]
+    fn clock_override_drives_ages() {
+        let mut t = Tracker::new();
+        t.set_clock_ms(1000);
+        touch(&mut t, 0, 1, 10, 10);
+        t.set_clock_ms(1150);
+        let f = t.on_syn_report();
+        assert_eq!(f.timestamp_ms, 1150);
+        assert_eq!(f.slots[0].age_ms, 150);
+    }
+
+    #[test]
+    fn clock_going_back_ages_nothing() {
+        let mut t = Tracker::new();
+        t.set_clock_ms(1000);
+        touch(&mut t, 0, 1, 10, 10);
+        t.set_clock_ms(900);
+        let f = t.on_syn_report();
+        assert_eq!(f.timestamp_ms, 900);
+        assert_eq!(f.slots[0].age_ms, 0);
+    }
+}